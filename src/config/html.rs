@@ -49,14 +49,6 @@ pub fn generate_html(conf: Generate) {
         .unwrap_or_else(|| "index.html".to_string());
     let html_dir = format!("{output}/{html_filename}");
 
-    // If the HTML file already exists, remove it
-    if fs::File::open(&html_dir).is_ok() {
-        if let Err(e) = fs::remove_file(&html_dir) {
-            eprintln!("Error removing existing file {html_dir}: {e}");
-            return;
-        }
-    }
-
     // If the output directory does not exist, create it
     if fs::read_dir(&output).is_err() {
         if let Err(e) = fs::create_dir(&output) {
@@ -65,11 +57,17 @@ pub fn generate_html(conf: Generate) {
         }
     }
 
-    // Write the HTML to the file
-    if let Err(e) = fs::File::create(&html_dir) {
-        eprintln!("Error creating file {html_dir}: {e}");
-        return;
-    }
+    let mut config = conf.config;
+    config.page = Some(html_filename);
+
+    let html = match config.to_html() {
+        Ok(html) => html,
+        Err(e) => {
+            eprintln!("Error generating {html_dir}: {e}");
+            return;
+        }
+    };
 
-    str_to_fs(&html_dir, &conf.config.to_html());
+    // Write the HTML to the file, replacing any existing one in a single step
+    str_to_fs(&html_dir, &html);
 }
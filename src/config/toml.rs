@@ -1,4 +1,5 @@
 use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
 
 // Define a main content struct
 #[derive(Serialize, Deserialize, Debug, Clone, Default)]
@@ -19,6 +20,8 @@ pub struct Object {
     pub id: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub is_document: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub children: Option<Vec<Object>>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, Default)]
@@ -35,12 +38,240 @@ pub struct Conf {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub rights: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub subtitle: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub base: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub lang: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub base_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub categories: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub feed_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_items: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub excerpt_length: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output_file: Option<String>,
 
+    // Markdown rendering
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub highlight: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code_line_numbers: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub toc: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub smart_punctuation: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lazy_images: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub external_links_new_tab: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub emoji: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub math: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub minify: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reading_time: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sanitize_html: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub robots: Option<Vec<RobotsRule>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub twitter_handle: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deploy: Option<Deploy>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub include: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nav: Option<Vec<NavItem>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub meta: Option<Vec<MetaTag>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub footer: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub favicon: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub theme: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stylesheets: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scripts: Option<Vec<Script>>,
+    // Inserted into the head verbatim, so it is not escaped and must be valid HTML
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub head_raw: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub main: Option<Main>,
+
+    // The page being rendered, used to mark its navigation link as current
+    #[serde(skip)]
+    pub page: Option<String>,
+
+    // Structured data describing the page being rendered, placed in the head as JSON-LD
+    #[serde(skip)]
+    pub structured_data: Option<String>,
+
+    // The folders wikilinks are resolved against, documents/ in the working directory when unset
+    #[serde(skip)]
+    pub document_dirs: Option<Vec<PathBuf>>,
+}
+
+// A meta tag placed in the head, identified by either a name or a property
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct MetaTag {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub property: Option<String>,
+    pub content: String,
+}
+
+// A group of robots.txt rules for the crawlers matching a user agent
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct RobotsRule {
+    pub user_agent: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub allow: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub disallow: Option<Vec<String>>,
+}
+
+// Where `adduce feed deploy` publishes the export directory to
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Deploy {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rsync: Option<RsyncDeploy>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub git: Option<GitDeploy>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub s3: Option<S3Deploy>,
+}
+
+// A server reached over rsync, such as `host = "user@example.com"` and `path = "/var/www"`
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct RsyncDeploy {
+    pub host: String,
+    pub path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub flags: Option<Vec<String>>,
+}
+
+// A branch the export is committed to, such as one served by GitHub Pages
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct GitDeploy {
+    // Defaults to the feed directory
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub repo: Option<String>,
+    pub branch: String,
+    // Defaults to origin
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub remote: Option<String>,
+}
+
+// An S3 bucket uploaded to with the AWS CLI, using its usual credentials
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct S3Deploy {
+    pub bucket: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub region: Option<String>,
+    // A folder within the bucket, such as `blog`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prefix: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cache_control: Option<String>,
+}
+
+// A script loaded at the end of the body
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Script {
+    pub src: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub defer: Option<bool>,
+}
+
+impl Conf {
+    // Method to turn a path relative to the site into an absolute URL using base_url
+    pub fn absolute(&self, path: &str) -> String {
+        let path = path
+            .split('/')
+            .filter(|segment| !segment.is_empty())
+            .collect::<Vec<_>>()
+            .join("/");
+        match &self.base_url {
+            Some(base_url) => format!("{}/{path}", base_url.trim_end_matches('/')),
+            None => format!("/{path}"),
+        }
+    }
+}
+
+// A link in the site navigation menu
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct NavItem {
+    pub label: String,
+    pub href: String,
+}
+
+// Per-document metadata declared in front matter
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct FrontMatter {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lang: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub toc: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub date: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub draft: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub enclosure: Option<Enclosure>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub categories: Option<Vec<String>>,
+    // Shown when the document is shared, as a URL or a path under base_url
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub image: Option<String>,
+}
+
+// Media attached to a document, such as a podcast episode
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Enclosure {
+    pub url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub length: Option<u64>,
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub mime_type: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn absolute_joins_paths_onto_base_url() {
+        let conf = Conf {
+            base_url: Some("https://example.com/blog/".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            conf.absolute("post.html"),
+            "https://example.com/blog/post.html"
+        );
+        assert_eq!(
+            conf.absolute("/images//cover.png"),
+            "https://example.com/blog/images/cover.png"
+        );
+        assert_eq!(Conf::default().absolute("post.html"), "/post.html");
+    }
 }
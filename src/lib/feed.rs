@@ -1,8 +1,32 @@
-use crate::config::toml::{Conf, Main, Object};
-use crate::lib::filesystem::fs_to_str;
-use atom_syndication::{ContentBuilder, EntryBuilder, FeedBuilder, GeneratorBuilder, Text};
-use chrono::Utc;
-use std::{env, fs, process::Command};
+use crate::config::toml::{
+    Conf, Enclosure, FrontMatter, GitDeploy, Main, MetaTag, Object, RobotsRule, RsyncDeploy,
+    S3Deploy,
+};
+use crate::lib::filesystem::{
+    content_type, mime_type, resolve_conf, with_trailing_newline, write_atomic,
+};
+use crate::lib::frontmatter;
+use crate::lib::parse::{escape_html, excerpt, plain_text, slugify, unescape_html, EXCERPT_MARKER};
+use atom_syndication::{
+    CategoryBuilder, ContentBuilder, EntryBuilder, FeedBuilder, GeneratorBuilder, Link,
+    LinkBuilder, Person, PersonBuilder, Text,
+};
+use chrono::{DateTime, Local, NaiveDate, Utc};
+use glob::Pattern;
+use notify::{EventKind, RecursiveMode, Watcher};
+use percent_encoding::percent_decode_str;
+use regex::RegexBuilder;
+use serde::Serialize;
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    env, fs,
+    io::{self, BufRead, BufReader, Cursor, IsTerminal, Write},
+    path::{Component, Path, PathBuf},
+    process::{self, Command, ExitStatus},
+    sync::mpsc,
+    time::Duration,
+};
+use tiny_http::{Header, Response, Server};
 use toml::de::Error as TomlError;
 
 const HELP: &str = r#"
@@ -12,274 +36,4841 @@ Usage: adduce feed [COMMAND] <argument>
 
 Commands:
     establish                   create directory structure
+    validate                    check conf.toml for errors
+    clean [--dry-run]           remove generated files from the export directory
     create <document_name>      create new document
-    remove <document_name>      delete a document
+        --template <template>   start from templates/<template>.md
+        --raw                   use the name as given instead of a slug
+        --dated                 prefix the name with today's date
+    remove <document_name>      delete a document, or those matching a
+                                glob pattern such as '2021-*'
+        --yes                   skip the confirmation prompt
+    rename <old_name> <new_name>
+                                rename a document
+    copy <source> <destination> start a new document from an existing one
     edit <document_name>        modify an existing document
+    open <document_name>        view an exported document in your browser
+        --yes                   export it first without asking if needed
     export <document_name>      generate HTML from document
-    search <query>              search your documents
+    export --all                generate HTML from every document
+        --force                 overwrite documents that were already exported
+    watch                       re-export documents as they change
+    serve [--port <port>]       preview the export directory locally
+    search <query>              search your documents, ignoring case
+        --case-sensitive        match case exactly
+        --regex                 treat the query as a regular expression
+    list [--exported]           list your documents
+    stats <document_name>       count words and estimate reading time
+    stats --all                 show statistics for every document
     atom                        generate Atom feed
+    json                        generate JSON feed
+    sitemap                     generate sitemap.xml from the exported documents
+    robots                      generate robots.txt from conf.toml
+    deploy rsync                upload the export directory with rsync
+        --dry-run               show what would change without uploading
+    deploy git                  commit the export directory to a branch and push it
+    deploy s3                   upload the export directory to an S3 bucket
+        --delete                remove objects that are no longer exported
+
+Options:
+    --dir <path>                use the feed in <path> rather than ./feed,
+                                also set with ADDUCE_FEED_DIR
+    --follow-symlinks           also read documents from folders linked into
+                                the documents folder
+    --drafts                    include drafts when exporting all documents,
+                                watching, and generating feeds
+    -q, --quiet                 only print errors and the results of a command
+    -v, --verbose               also print details about each file
+
+Shortcodes:
+    {{youtube id="<video_id>"}} embed a YouTube video
+    {{figure src="<image>" caption="<caption>" alt="<alt>"}}
+                                an image with a caption
+    {{gist id="<user>/<gist_id>"}}
+                                embed a GitHub gist
 
 See `adduce` for creating individual pages.
 "#;
 
-pub fn process(args: Vec<String>) {
+// Options that take a value, such as `--port 8080`
+const VALUE_FLAGS: &[&str] = &["--dir", "--port", "--template"];
+
+// How much informational output to print
+const QUIET: u8 = 0;
+const NORMAL: u8 = 1;
+const VERBOSE: u8 = 2;
+
+// Print a progress or status line, unless running quietly
+macro_rules! info {
+    ($feed:expr, $($arg:tt)*) => {
+        $feed.log(NORMAL, &format!($($arg)*))
+    };
+}
+
+// Print details about individual files, only when running verbosely
+macro_rules! detail {
+    ($feed:expr, $($arg:tt)*) => {
+        $feed.log(VERBOSE, &format!($($arg)*))
+    };
+}
+
+// The feed a command works on, found in its own directory, and how the command was asked to run
+struct Feed {
+    root: PathBuf,
+    follow_symlinks: bool,
+    verbosity: u8,
+    // Where informational lines are printed, which is standard output outside of tests
+    output: Box<dyn Fn(&str)>,
+}
+
+impl Feed {
+    // A feed in the given directory, printing informational lines as usual
+    fn new(root: PathBuf) -> Feed {
+        Feed {
+            root,
+            follow_symlinks: false,
+            verbosity: NORMAL,
+            output: Box::new(|line| println!("{line}")),
+        }
+    }
+
+    // Print an informational line if running at least as verbosely as its level
+    fn log(&self, level: u8, line: &str) {
+        if self.verbosity >= level {
+            (self.output)(line);
+        }
+    }
+
+    // Path of a file or folder inside the feed, such as conf.toml or export/
+    fn path(&self, relative: impl AsRef<Path>) -> PathBuf {
+        self.root.join(relative)
+    }
+
+    // Path of a document's Markdown source, which may be in a folder linked into documents/
+    fn document_path(&self, document: &str) -> PathBuf {
+        let file_name = format!("{document}.md");
+        let dirs = self.document_dirs();
+        dirs.iter()
+            .map(|dir| dir.join(&file_name))
+            .find(|path| path.exists())
+            .unwrap_or_else(|| dirs[0].join(&file_name))
+    }
+
+    // Folders documents are read from: documents/ itself, then any folders linked into it when following links
+    fn document_dirs(&self) -> Vec<PathBuf> {
+        let documents = self.path("documents");
+        let mut dirs = vec![documents.clone()];
+        let mut visited = documents.canonicalize().into_iter().collect();
+        linked_dirs(self, &documents, &mut visited, &mut dirs);
+        dirs
+    }
+
+    // Path of a document's exported HTML
+    fn export_path(&self, document: &str) -> PathBuf {
+        self.path("export").join(format!("{document}.html"))
+    }
+}
+
+// Collect the folders linked into a folder, and those linked into them, reading each folder once so a
+// link back to one already read cannot lead into a cycle
+fn linked_dirs(feed: &Feed, dir: &Path, visited: &mut HashSet<PathBuf>, dirs: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    let mut links = entries
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.is_symlink() && path.is_dir() && !is_hidden(path))
+        .collect::<Vec<_>>();
+    links.sort();
+
+    for link in links {
+        if !feed.follow_symlinks {
+            detail!(
+                feed,
+                "Skipping {}, a linked folder. Pass --follow-symlinks to read it.",
+                link.display()
+            );
+            continue;
+        }
+        let Ok(target) = link.canonicalize() else {
+            continue;
+        };
+        if !visited.insert(target) {
+            detail!(
+                feed,
+                "Skipping {}, which links to a folder that has already been read.",
+                link.display()
+            );
+            continue;
+        }
+        dirs.push(link.clone());
+        linked_dirs(feed, &link, visited, dirs);
+    }
+}
+
+// Directory of the feed, chosen by --dir, then ADDUCE_FEED_DIR, and otherwise `feed`
+fn feed_root(dir: Option<&str>, env_dir: Option<String>) -> PathBuf {
+    dir.map(str::to_string)
+        .or(env_dir)
+        .filter(|dir| !dir.is_empty())
+        .map_or_else(|| PathBuf::from("feed"), PathBuf::from)
+}
+
+pub fn process(args: Vec<String>) -> Result<(), String> {
     if args.len() < 2 {
         println!("{HELP}");
-        return;
+        return Ok(());
+    }
+
+    // Separate `--flag` options from the command and its positional arguments
+    let mut flags = Vec::new();
+    let mut arguments = Vec::new();
+    let mut values = HashMap::new();
+    let mut rest = args[1..].iter().map(String::as_str);
+    while let Some(arg) = rest.next() {
+        if VALUE_FLAGS.contains(&arg) {
+            if let Some(value) = rest.next() {
+                values.insert(arg, value);
+            }
+        } else if arg.starts_with("--") {
+            flags.push(arg);
+        } else if let Some(flag) = short_flag(arg) {
+            flags.push(flag);
+        } else {
+            arguments.push(arg);
+        }
+    }
+
+    if arguments.is_empty() {
+        println!("{HELP}");
+        return Ok(());
+    }
+    let command = arguments.remove(0);
+
+    // Every path is relative to the feed's directory, including those in conf.toml
+    let mut feed = Feed::new(feed_root(
+        values.get("--dir").copied(),
+        env::var("ADDUCE_FEED_DIR").ok(),
+    ));
+    feed.follow_symlinks = flags.contains(&"--follow-symlinks");
+    if flags.contains(&"--quiet") {
+        feed.verbosity = QUIET;
+    } else if flags.contains(&"--verbose") {
+        feed.verbosity = VERBOSE;
+    }
+
+    // Document names are joined onto folder paths, so they must not be able to escape them
+    let takes_names = matches!(
+        command,
+        "remove" | "rename" | "copy" | "edit" | "open" | "export" | "stats"
+    );
+    if takes_names {
+        if let Some(name) = arguments.iter().find(|name| !is_valid_document_name(name)) {
+            return Err(format!(
+                "Invalid document name '{name}': names cannot contain path separators."
+            ));
+        }
     }
 
-    let command = args[1].as_str();
+    let include_drafts = flags.contains(&"--drafts");
 
     match command {
-        "establish" => cli_establish(),
-        "atom" => cli_atom(),
-        "create" | "remove" | "edit" | "export" | "search" => {
-            if args.len() < 3 {
+        "establish" => cli_establish(&feed),
+        "validate" => cli_validate(&feed),
+        "clean" => cli_clean(&feed, flags.contains(&"--dry-run")),
+        "atom" => cli_atom(&feed, include_drafts),
+        "json" => cli_json(&feed, include_drafts),
+        "sitemap" => cli_sitemap(&feed),
+        "robots" => cli_robots(&feed),
+        "rename" | "copy" => {
+            if arguments.len() < 2 {
+                println!("{HELP}");
+                return Ok(());
+            }
+            if command == "rename" {
+                cli_rename(&feed, arguments[0], arguments[1])
+            } else {
+                cli_copy(&feed, arguments[0], arguments[1])
+            }
+        }
+        "list" => cli_list(&feed, flags.contains(&"--exported")),
+        "export" if flags.contains(&"--all") => {
+            cli_export_all(&feed, include_drafts, flags.contains(&"--force"))
+        }
+        "stats" if flags.contains(&"--all") => cli_stats_all(&feed),
+        "watch" => cli_watch(&feed, include_drafts),
+        "serve" => cli_serve(&feed, values.get("--port").copied()),
+        "create" | "remove" | "edit" | "open" | "export" | "search" | "stats" | "deploy" => {
+            if arguments.is_empty() {
                 println!("{HELP}");
-                return;
+                return Ok(());
             }
-            let argument = args[2].as_str();
+            let argument = arguments[0];
             match command {
-                "create" => cli_create(argument),
-                "remove" => cli_remove(argument),
-                "edit" => cli_edit(argument),
-                "export" => cli_export(argument),
-                "search" => cli_search(argument),
-                _ => println!("{HELP}"),
+                "create" => cli_create(
+                    &feed,
+                    argument,
+                    values.get("--template").copied(),
+                    flags.contains(&"--raw"),
+                    flags.contains(&"--dated"),
+                ),
+                "remove" => cli_remove(&feed, argument, flags.contains(&"--yes")),
+                "edit" => cli_edit(&feed, argument),
+                "open" => cli_open(&feed, argument, flags.contains(&"--yes")),
+                "export" => cli_export(&feed, argument, flags.contains(&"--force")),
+                "stats" => cli_stats(&feed, argument),
+                "deploy" => cli_deploy(
+                    &feed,
+                    argument,
+                    flags.contains(&"--dry-run"),
+                    flags.contains(&"--delete"),
+                ),
+                "search" => cli_search(
+                    &feed,
+                    argument,
+                    flags.contains(&"--case-sensitive"),
+                    flags.contains(&"--regex"),
+                ),
+                _ => unreachable!(),
             }
         }
-        _ => println!("{HELP}"),
+        _ => {
+            println!("{HELP}");
+            Err(format!("Unknown command '{command}'."))
+        }
+    }
+}
+
+// Expand the short form of an option, such as `-q` for `--quiet`
+fn short_flag(arg: &str) -> Option<&'static str> {
+    match arg {
+        "-q" => Some("--quiet"),
+        "-v" => Some("--verbose"),
+        _ => None,
     }
 }
 
 // Create the required directory structure
-fn cli_establish() {
-    for dir in &["documents", "export"] {
-        if fs::read_dir(dir).is_err() {
-            println!("Creating {dir}...");
-            fs::create_dir(dir).expect("Failed to create {dir}.");
+fn cli_establish(feed: &Feed) -> Result<(), String> {
+    for dir in ["documents", "export"] {
+        let dir = feed.path(dir);
+        if dir.is_dir() {
+            continue;
         }
+        info!(feed, "Creating {}...", dir.display());
+        fs::create_dir_all(&dir).map_err(|e| format!("Failed to create {}: {e}", dir.display()))?;
     }
+
+    // Leave any existing configuration alone
+    let conf_path = feed.path("conf.toml");
+    if conf_path.exists() {
+        return Ok(());
+    }
+    info!(feed, "Creating {}...", conf_path.display());
+    fs::write(&conf_path, STARTER_CONF)
+        .map_err(|e| format!("Failed to create {}: {e}", conf_path.display()))
 }
 
-// Create a new document
-fn cli_create(filename: &str) {
-    let folder_path = "documents";
-    let file_path = format!("{folder_path}/{filename}.md");
+// Configuration written by establish, with every field a feed needs filled in
+const STARTER_CONF: &str = r#"# The title of your site, used for pages and feeds
+title = "My Site"
 
-    if !fs::metadata(folder_path).is_ok() {
-        eprintln!("The documents folder does not exist. Please run `adduce feed establish` to create the necessary file structure.");
-        return;
-    }
+# A permanent, unique identifier for your feed, usually your site's address
+id = "https://example.com/"
 
-    if fs::metadata(&file_path).is_ok() {
-        eprintln!("Document already exists: {file_path}.");
-        return;
-    }
+# A short description of your site
+subtitle = "A site generated by Adduce"
 
-    let initial_content = format!("# {filename}\n");
-    if let Err(err) = fs::write(&file_path, initial_content) {
-        eprintln!("Failed to create file {file_path}: {err}.");
-        return;
+# The address documents are published under
+base = "https://example.com/"
+
+# The language your documents are written in
+lang = "en"
+
+# Each exported page is built from these blocks in order
+# The "document" block is replaced by the contents of the document
+[[main.block]]
+format = "document"
+"#;
+
+// Check that conf.toml parses and has the fields the feeds require
+fn cli_validate(feed: &Feed) -> Result<(), String> {
+    let conf_path = feed.path("conf.toml");
+    let content = fs::read_to_string(&conf_path)
+        .map_err(|e| format!("Error reading configuration file: {e}"))?;
+    let conf = toml::from_str::<Conf>(&content)
+        .map_err(|e| format!("Error parsing configuration file: {e}"))?;
+    let conf =
+        resolve_conf(conf, &conf_path).map_err(|e| format!("Error in configuration file: {e}"))?;
+
+    let missing_fields = missing_feed_fields(&conf);
+    if !missing_fields.is_empty() {
+        return Err(format!(
+            "Missing fields required for feeds: {}.",
+            missing_fields.join(", ")
+        ));
     }
 
-    println!("Created new file: {file_path}.");
+    info!(feed, "conf.toml is valid.");
+    Ok(())
 }
 
-// Remove a requested document
-fn cli_remove(filename: &str) {
-    let md_file_path = format!("documents/{filename}.md");
-    if let Err(error) = fs::remove_file(&md_file_path) {
-        println!("Error removing source document {filename}: {error}.");
-    } else {
-        println!("Deleted source document '{filename}'.");
+// Publish the export directory using one of the configured deploy methods
+fn cli_deploy(feed: &Feed, method: &str, dry_run: bool, delete: bool) -> Result<(), String> {
+    let conf = export_conf(feed)?;
+    let deploy = conf.deploy.unwrap_or_default();
+    match method {
+        "rsync" => {
+            let rsync = deploy
+                .rsync
+                .ok_or("No [deploy.rsync] section in conf.toml.")?;
+            run_deploy(feed, "rsync", &rsync_args(feed, &rsync, dry_run))
+        }
+        "git" => {
+            let git = deploy.git.ok_or("No [deploy.git] section in conf.toml.")?;
+            deploy_git(feed, &git)
+        }
+        "s3" => {
+            let s3 = deploy.s3.ok_or("No [deploy.s3] section in conf.toml.")?;
+            deploy_s3(feed, &s3, delete)
+        }
+        _ => Err(format!(
+            "Unknown deploy method '{method}'. Expected rsync, git, or s3."
+        )),
     }
+}
 
-    let html_file_path = format!("export/{filename}.html");
-    if let Err(error) = fs::remove_file(&html_file_path) {
-        println!("Error removing exported document {filename}: {error}.");
-    } else {
-        println!("Deleted exported document '{filename}'.");
+// Arguments that mirror the export directory to the configured server
+fn rsync_args(feed: &Feed, rsync: &RsyncDeploy, dry_run: bool) -> Vec<String> {
+    let mut args = vec!["-az".to_string(), "--delete".to_string()];
+    if dry_run {
+        args.push("-n".to_string());
     }
+    args.extend(rsync.flags.iter().flatten().cloned());
+    // The trailing slash copies the contents of export/ rather than the folder itself
+    args.push(format!("{}/", feed.path("export").display()));
+    args.push(format!("{}:{}", rsync.host, rsync.path));
+    args
 }
 
-// Edit a requested document
-fn cli_edit(filename: &str) {
-    let file_path = format!("documents/{filename}.md");
+// Run a deploy tool, printing the command first so it can be repeated by hand
+fn run_deploy(feed: &Feed, program: &str, args: &[String]) -> Result<(), String> {
+    info!(feed, "{program} {}", args.join(" "));
+    match Command::new(program).args(args).status() {
+        Ok(status) if status.success() => {
+            info!(feed, "Deployed successfully.");
+            Ok(())
+        }
+        Ok(status) => Err(format!("{program} exited with {status}.")),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Err(format!(
+            "{program} is not installed. Install it to deploy with this method."
+        )),
+        Err(e) => Err(format!("Failed to run {program}: {e}.")),
+    }
+}
 
-    if fs::read(&file_path).is_err() {
-        println!("No documents with that name.");
-        return;
+// Commit the export directory to a branch through a separate worktree, leaving the current checkout alone
+fn deploy_git(feed: &Feed, config: &GitDeploy) -> Result<(), String> {
+    let repo = &feed.path(config.repo.as_deref().unwrap_or("."));
+    let branch = config.branch.as_str();
+    let remote = config.remote.as_deref().unwrap_or("origin");
+    if !feed.path("export").is_dir() {
+        return Err("The export folder does not exist. Export your documents first.".to_string());
     }
 
-    let editor_command = env::var("EDITOR").unwrap_or_else(|_| "notepad".to_string());
+    let worktree = env::temp_dir().join(format!("adduce-deploy-{}", process::id()));
+    let worktree_arg = worktree.to_string_lossy().to_string();
+    let new_branch = if git_ref_exists(feed, repo, &format!("refs/heads/{branch}")) {
+        git(feed, repo, &["worktree", "add", &worktree_arg, branch])?;
+        false
+    } else if git_ref_exists(feed, repo, &format!("refs/remotes/{remote}/{branch}")) {
+        // Build on what is already published, so the push is a fast-forward
+        let upstream = format!("{remote}/{branch}");
+        git(
+            feed,
+            repo,
+            &["worktree", "add", "-b", branch, &worktree_arg, &upstream],
+        )?;
+        false
+    } else {
+        // A new branch starts empty, without the history of whatever is checked out
+        git(feed, repo, &["worktree", "add", "--detach", &worktree_arg])?;
+        true
+    };
+
+    // The worktree is removed whether or not the deploy worked, so nothing is left behind
+    let result = commit_export(feed, &worktree, branch, new_branch).and_then(|committed| {
+        if committed {
+            info!(feed, "Pushing {branch} to {remote}...");
+            git(feed, &worktree, &["push", remote, branch]).map(|_| ())
+        } else {
+            info!(
+                feed,
+                "Nothing to deploy; {branch} already matches the export."
+            );
+            Ok(())
+        }
+    });
+    let removed = git(
+        feed,
+        repo,
+        &["worktree", "remove", "--force", &worktree_arg],
+    );
 
-    Command::new(editor_command)
-        .arg(file_path)
-        .spawn()
-        .expect("Failed to launch editor.")
-        .wait()
-        .expect("Editor exited with error.");
+    result?;
+    removed?;
+    info!(feed, "Deployed successfully.");
+    Ok(())
 }
 
-// Generate a HTML version of the input document
-fn cli_export(document: &str) {
-    let md_file_path = format!("documents/{document}.md");
-    if fs::metadata(&md_file_path).is_err() {
-        println!("Input file '{document}' does not exist. Please create it first.");
-        return;
+// Replace a worktree's files with the export and commit them, returning whether anything changed
+fn commit_export(
+    feed: &Feed,
+    worktree: &Path,
+    branch: &str,
+    new_branch: bool,
+) -> Result<bool, String> {
+    if new_branch {
+        git(feed, worktree, &["checkout", "--quiet", "--orphan", branch])?;
     }
 
-    let conf = match fs::read_to_string("conf.toml") {
-        Ok(content) => toml::from_str::<Conf>(&content).unwrap(),
-        Err(e) => {
-            println!("{e}\nYou must manually create a conf.toml file for your feed.");
-            return;
+    let entries = fs::read_dir(worktree)
+        .map_err(|e| format!("Failed to read {}: {e}", worktree.display()))?;
+    for entry in entries.filter_map(Result::ok) {
+        let path = entry.path();
+        if entry.file_name() == ".git" {
+            continue;
         }
-    };
+        let removed = if path.is_dir() {
+            fs::remove_dir_all(&path)
+        } else {
+            fs::remove_file(&path)
+        };
+        removed.map_err(|e| format!("Failed to remove {}: {e}", path.display()))?;
+    }
+    copy_dir(&feed.path("export"), worktree)
+        .map_err(|e| format!("Failed to copy the export directory: {e}"))?;
 
-    let content = fs_to_str(&md_file_path);
-    let md_object = Object {
-        format: Some("md".to_string()),
-        content: Some(content),
-        ..Default::default()
-    };
+    git(feed, worktree, &["add", "--all"])?;
+    if git(feed, worktree, &["diff", "--cached", "--quiet"]).is_ok() && !new_branch {
+        return Ok(false);
+    }
+    let message = format!("Deploy {}", Local::now().format("%Y-%m-%d %H:%M:%S"));
+    git(
+        feed,
+        worktree,
+        &["commit", "--quiet", "--allow-empty", "-m", &message],
+    )?;
+    Ok(true)
+}
 
-    let mut toml = conf;
-    if let Some(main) = toml.main.as_mut() {
-        let position = main
-            .block
-            .iter()
-            .position(|obj| obj.format.as_deref() == Some("document"));
+// Check whether a repository has a ref, such as a local or remote-tracking branch
+fn git_ref_exists(feed: &Feed, repo: &Path, reference: &str) -> bool {
+    git(feed, repo, &["rev-parse", "--verify", "--quiet", reference]).is_ok()
+}
 
-        if let Some(pos) = position {
-            main.block[pos] = md_object;
-        } else {
-            main.block.push(md_object);
+// Run git in a directory, returning its output or what went wrong
+fn git(feed: &Feed, dir: &Path, args: &[&str]) -> Result<String, String> {
+    detail!(feed, "git -C {} {}", dir.display(), args.join(" "));
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .output()
+        .map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => {
+                "git is not installed. Install it to deploy with this method.".to_string()
+            }
+            _ => format!("Failed to run git: {e}."),
+        })?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!("git {} failed: {}", args.join(" "), stderr.trim()));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+// Upload every exported file to S3 with its content type, optionally removing what is no longer exported
+fn deploy_s3(feed: &Feed, config: &S3Deploy, delete: bool) -> Result<(), String> {
+    sync_bucket(feed, &AwsCli::connect(feed, config), config, delete)
+}
+
+// Mirror the export directory into a bucket under the configured prefix
+fn sync_bucket(
+    feed: &Feed,
+    bucket: &impl Bucket,
+    config: &S3Deploy,
+    delete: bool,
+) -> Result<(), String> {
+    let export = feed.path("export");
+    let mut files = Vec::new();
+    list_files(&export, &mut files)
+        .map_err(|e| format!("Failed to read the export directory: {e}"))?;
+    files.sort();
+
+    let prefix = config
+        .prefix
+        .as_deref()
+        .map(|prefix| prefix.trim_matches('/'))
+        .filter(|prefix| !prefix.is_empty());
+    let key = |path: &Path| {
+        let relative = path.strip_prefix(&export).unwrap_or(path);
+        let relative = relative
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        match prefix {
+            Some(prefix) => format!("{prefix}/{relative}"),
+            None => relative,
         }
-    } else {
-        toml.main = Some(Main {
-            block: vec![md_object],
-        });
+    };
+
+    let mut keys = BTreeSet::new();
+    for path in &files {
+        let key = key(path);
+        let body = fs::read(path).map_err(|e| format!("Failed to read {}: {e}", path.display()))?;
+        let content_type = content_type(&path.to_string_lossy());
+        detail!(
+            feed,
+            "Uploading {} as {key} ({content_type}).",
+            path.display()
+        );
+        bucket.put(&key, body, &content_type, config.cache_control.as_deref())?;
+        info!(feed, "Uploaded {key}.");
+        keys.insert(key);
     }
 
-    if let Err(err) = fs::write(format!("export/{document}.html"), toml.to_html()) {
-        eprintln!("Failed to export {document}: {err}.");
-        return;
+    if delete {
+        let listed = bucket.list(prefix.map(|prefix| format!("{prefix}/")).as_deref())?;
+        for key in listed.into_iter().filter(|key| !keys.contains(key)) {
+            bucket.delete(&key)?;
+            info!(feed, "Deleted {key}.");
+        }
     }
 
-    println!("Successfully exported {document}.");
+    info!(feed, "Deployed {} files to {}.", files.len(), config.bucket);
+    Ok(())
 }
 
-// Search documents
-fn cli_search(keyword: &str) {
-    let entries = fs::read_dir("documents/")
-        .expect("Failed to read documents directory.")
-        .filter_map(|entry| {
-            entry
-                .ok()
-                .map(|e| e.file_name().into_string().unwrap_or_default())
-        });
+// What deploying needs from a bucket, so the upload can be checked without reaching AWS
+trait Bucket {
+    fn put(
+        &self,
+        key: &str,
+        body: Vec<u8>,
+        content_type: &str,
+        cache_control: Option<&str>,
+    ) -> Result<(), String>;
+    fn list(&self, prefix: Option<&str>) -> Result<Vec<String>, String>;
+    fn delete(&self, key: &str) -> Result<(), String>;
+}
 
-    let mut found_results = false;
+// A bucket reached through the AWS CLI, which finds credentials and the region the usual AWS ways
+struct AwsCli<'a> {
+    feed: &'a Feed,
+    bucket: &'a str,
+    region: Option<&'a str>,
+}
 
-    for entry in entries {
-        if entry.contains(keyword) {
-            println!("{entry}");
-            found_results = true;
+impl<'a> AwsCli<'a> {
+    fn connect(feed: &'a Feed, config: &'a S3Deploy) -> AwsCli<'a> {
+        AwsCli {
+            feed,
+            bucket: &config.bucket,
+            region: config.region.as_deref(),
         }
     }
 
-    if !found_results {
-        println!("No results found for '{keyword}'.");
+    // Run the AWS CLI against this bucket's region, returning its output
+    fn run(&self, args: &[&str]) -> Result<String, String> {
+        let mut args = args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+        if let Some(region) = self.region {
+            args.extend(["--region".to_string(), region.to_string()]);
+        }
+        aws(self.feed, &args)
     }
 }
 
-// TODO: Allow front matter in documents so it can be referenced here:
+impl Bucket for AwsCli<'_> {
+    fn put(
+        &self,
+        key: &str,
+        body: Vec<u8>,
+        content_type: &str,
+        cache_control: Option<&str>,
+    ) -> Result<(), String> {
+        // The CLI uploads from a file, so the body is staged in a temporary one
+        let staged = env::temp_dir().join(format!("adduce-upload-{}", process::id()));
+        fs::write(&staged, body).map_err(|e| format!("Failed to stage {key}: {e}"))?;
+        let source = staged.to_string_lossy().to_string();
+        let destination = format!("s3://{}/{key}", self.bucket);
+        let mut args = vec![
+            "s3",
+            "cp",
+            &source,
+            &destination,
+            "--content-type",
+            content_type,
+        ];
+        if let Some(cache_control) = cache_control {
+            args.extend(["--cache-control", cache_control]);
+        }
+        let result = self.run(&args).map(|_| ());
+        let _ = fs::remove_file(&staged);
+        result
+    }
+
+    fn list(&self, prefix: Option<&str>) -> Result<Vec<String>, String> {
+        let mut args = vec![
+            "s3api",
+            "list-objects-v2",
+            "--bucket",
+            self.bucket,
+            "--query",
+            "Contents[].Key",
+            "--output",
+            "json",
+        ];
+        if let Some(prefix) = prefix {
+            args.extend(["--prefix", prefix]);
+        }
 
-// Generate an Atom feed
-fn cli_atom() {
-    let mut entries = Vec::new();
+        // The listing is null rather than an empty list when nothing matches
+        let listing = self.run(&args)?;
+        serde_json::from_str::<Option<Vec<String>>>(&listing)
+            .map(Option::unwrap_or_default)
+            .map_err(|e| format!("Could not read the bucket listing: {e}"))
+    }
 
-    for entry in fs::read_dir("export/").unwrap() {
-        let entry = entry.unwrap();
-        let path = entry.path();
-        let content = fs::read_to_string(&path).unwrap_or_default();
+    fn delete(&self, key: &str) -> Result<(), String> {
+        self.run(&["s3", "rm", &format!("s3://{}/{key}", self.bucket)])
+            .map(|_| ())
+    }
+}
 
-        if path.file_name().unwrap() == "feed.xml" {
+// Run the AWS CLI, explaining credential problems rather than passing on its raw error
+fn aws(feed: &Feed, args: &[String]) -> Result<String, String> {
+    detail!(feed, "aws {}", args.join(" "));
+    let output = Command::new("aws")
+        .args(args)
+        .output()
+        .map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => {
+                "The AWS CLI is not installed. Install it to deploy to S3.".to_string()
+            }
+            _ => format!("Failed to run aws: {e}."),
+        })?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let credentials = [
+            "Unable to locate credentials",
+            "InvalidAccessKeyId",
+            "ExpiredToken",
+            "SignatureDoesNotMatch",
+        ];
+        if credentials.iter().any(|error| stderr.contains(error)) {
+            return Err(format!(
+                "AWS rejected or could not find your credentials. Run `aws configure` or set AWS_PROFILE.\n{}",
+                stderr.trim()
+            ));
+        }
+        return Err(format!("aws {} failed: {}", args.join(" "), stderr.trim()));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+// Collect every file in a folder and its subfolders, leaving out hidden files
+fn list_files(dir: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if is_hidden(&path) {
             continue;
         }
+        if path.is_dir() {
+            list_files(&path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+// Copy the contents of a folder into another, including subfolders
+fn copy_dir(from: &Path, to: &Path) -> io::Result<()> {
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let destination = to.join(entry.file_name());
+        if entry.path().is_dir() {
+            fs::create_dir_all(&destination)?;
+            copy_dir(&entry.path(), &destination)?;
+        } else {
+            fs::copy(entry.path(), &destination)?;
+        }
+    }
+    Ok(())
+}
 
-        let entry = EntryBuilder::default()
-            .title(Text::plain(
-                path.file_name().unwrap().to_string_lossy().to_string(),
-            ))
-            .content(ContentBuilder::default().value(content).build())
-            .build();
+// Extensions of the files Adduce generates in the export directory
+const GENERATED_EXTENSIONS: &[&str] = &["html", "xml", "json", "txt"];
+
+// Remove generated files from the export directory, keeping subfolders such as assets
+fn cli_clean(feed: &Feed, dry_run: bool) -> Result<(), String> {
+    let entries = fs::read_dir(feed.path("export")).map_err(|_| {
+        "The export folder does not exist. Please run `adduce feed establish` to create the necessary file structure.".to_string()
+    })?;
 
-        entries.push(entry);
+    let mut generated = entries
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.is_file())
+        .filter(|path| {
+            path.extension()
+                .and_then(|extension| extension.to_str())
+                .is_some_and(|extension| GENERATED_EXTENSIONS.contains(&extension))
+        })
+        .collect::<Vec<_>>();
+    generated.sort();
+
+    if generated.is_empty() {
+        info!(feed, "Nothing to clean.");
+        return Ok(());
     }
 
-    let conf_content = match fs::read_to_string("conf.toml") {
-        Ok(content) => content,
-        Err(e) => {
-            println!("Error reading configuration file: {e}\nNo configuration file found.");
-            return;
+    let mut failed = 0;
+    for path in generated {
+        if dry_run {
+            println!("Would remove {}.", path.display());
+        } else if let Err(e) = fs::remove_file(&path) {
+            eprintln!("Error removing {}: {e}.", path.display());
+            failed += 1;
+        } else {
+            info!(feed, "Removed {}.", path.display());
         }
+    }
+
+    if failed > 0 {
+        return Err(format!("{failed} files could not be removed."));
+    }
+    Ok(())
+}
+
+// Create a new document
+fn cli_create(
+    feed: &Feed,
+    title: &str,
+    template: Option<&str>,
+    raw: bool,
+    dated: bool,
+) -> Result<(), String> {
+    let date = Local::now().format("%Y-%m-%d").to_string();
+    let mut filename = if raw {
+        title.to_string()
+    } else {
+        slugify(title)
     };
+    if !is_valid_document_name(&filename) {
+        return Err(format!("Cannot create a document named '{title}'."));
+    }
+    if dated {
+        filename = format!("{date}-{filename}");
+    }
 
-    let conf: Result<Conf, TomlError> = toml::from_str(&conf_content);
-    let conf = match conf {
-        Ok(conf) => conf,
-        Err(e) => {
-            println!("Error parsing configuration file: {e}");
-            return;
+    let file_path = feed.document_path(&filename);
+
+    if fs::metadata(feed.path("documents")).is_err() {
+        return Err("The documents folder does not exist. Please run `adduce feed establish` to create the necessary file structure.".to_string());
+    }
+
+    if fs::metadata(&file_path).is_ok() {
+        return Err(format!("Document already exists: {}.", file_path.display()));
+    }
+
+    let initial_content = match template {
+        None => format!("# {title}\n"),
+        Some(template) => {
+            let template_path = feed.path("templates").join(format!("{template}.md"));
+            fs::read_to_string(&template_path)
+                .map_err(|err| {
+                    format!(
+                        "Failed to read template {}: {err}.",
+                        template_path.display()
+                    )
+                })?
+                .replace("{{title}}", title)
+                .replace("{{date}}", &date)
         }
     };
 
-    if conf.title.is_none() || conf.id.is_none() {
-        let mut missing_fields = Vec::new();
+    let initial_content = if dated {
+        frontmatter::stamp_date(&initial_content, &date)
+    } else {
+        initial_content
+    };
+
+    fs::write(&file_path, initial_content)
+        .map_err(|err| format!("Failed to create file {}: {err}.", file_path.display()))?;
+
+    info!(feed, "Created new file: {}.", file_path.display());
+    Ok(())
+}
 
-        if conf.title.is_none() {
-            missing_fields.push("title");
+// Remove a requested document, or every document matching a glob pattern
+fn cli_remove(feed: &Feed, filename: &str, yes: bool) -> Result<(), String> {
+    if !filename.contains(['*', '?', '[']) {
+        let exists = feed.document_path(filename).exists() || feed.export_path(filename).exists();
+        if !exists {
+            return Err("No documents with that name.".to_string());
         }
-        if conf.id.is_none() {
-            missing_fields.push("id");
+        if !yes && !confirm(&format!("Remove '{filename}' and its export?")) {
+            info!(feed, "Nothing was removed.");
+            return Ok(());
         }
+        return remove_document(feed, filename);
+    }
 
-        println!(
-            "Atom feed not generated. Missing required fields: {}.",
-            missing_fields.join(", ")
-        );
-        return;
+    // Patterns are only matched against document names, so nothing outside documents/ and export/ is touched
+    let pattern =
+        Pattern::new(filename).map_err(|e| format!("Invalid pattern '{filename}': {e}."))?;
+    let documents =
+        document_names(feed).map_err(|e| format!("Failed to read the documents folder: {e}."))?;
+    let matched = documents
+        .into_iter()
+        .filter(|document| pattern.matches(document))
+        .collect::<Vec<_>>();
+
+    if matched.is_empty() {
+        return Err(format!("No documents match '{filename}'."));
     }
 
-    let generator = GeneratorBuilder::default()
-        .value("Adduce".to_string())
-        .uri("http://adduce.vale.rocks".to_string())
-        .version(env!("CARGO_PKG_VERSION").to_string())
-        .build();
+    info!(feed, "Matching documents:");
+    for document in &matched {
+        info!(feed, "    {document}");
+    }
+    if !yes
+        && !confirm(&format!(
+            "Remove these {} documents and their exports?",
+            matched.len()
+        ))
+    {
+        info!(feed, "Nothing was removed.");
+        return Ok(());
+    }
 
-    let feed = FeedBuilder::default()
-        .title(Text::plain(conf.title.unwrap()))
-        .id(conf.id.as_ref().unwrap())
-        .updated(Utc::now())
-        // TODO: Authors
-        // TODO: Categories
-        .generator(generator)
-        .icon(conf.icon)
-        // TODO: Links
-        .logo(conf.logo)
-        .rights(conf.rights.map(Text::plain))
-        .entries(entries)
-        .subtitle(conf.subtitle.map(Text::plain))
-        .base(conf.base)
-        .lang(conf.lang)
-        .build();
+    // Keep going after a failure so one stubborn file does not block the rest
+    let failed = matched
+        .iter()
+        .filter(|document| {
+            remove_document(feed, document)
+                .inspect_err(|e| eprintln!("{e}"))
+                .is_err()
+        })
+        .count();
+    if failed > 0 {
+        return Err(format!("{failed} documents could not be removed."));
+    }
+    Ok(())
+}
 
-    if let Err(e) = fs::write("export/feed.xml", feed.to_string()) {
-        eprintln!("Failed to write Atom feed: {e}");
-    } else {
-        println!("Atom feed generated successfully.");
+// Delete a document's source and export
+fn remove_document(feed: &Feed, filename: &str) -> Result<(), String> {
+    let source = remove_if_present(feed, &feed.document_path(filename), "source", filename);
+    let exported = remove_if_present(feed, &feed.export_path(filename), "exported", filename);
+    source.and(exported)
+}
+
+// Delete a file belonging to a document, where a file that is already gone needs no removing
+fn remove_if_present(feed: &Feed, path: &Path, kind: &str, filename: &str) -> Result<(), String> {
+    match fs::remove_file(path) {
+        Ok(()) => info!(feed, "Deleted {kind} document '{filename}'."),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            info!(feed, "No {kind} document '{filename}' to delete.");
+        }
+        Err(e) => return Err(format!("Error removing {kind} document {filename}: {e}.")),
+    }
+    Ok(())
+}
+
+// Ask the user to confirm an action, declining when there is nobody to ask
+fn confirm(question: &str) -> bool {
+    let stdin = io::stdin();
+    if !stdin.is_terminal() {
+        eprintln!("Not running interactively. Pass --yes to confirm.");
+        return false;
+    }
+
+    confirm_with(question, &mut stdin.lock())
+}
+
+// Ask for confirmation, reading the answer from the given input
+fn confirm_with(question: &str, input: &mut impl BufRead) -> bool {
+    print!("{question} Are you sure? [y/N] ");
+    if io::stdout().flush().is_err() {
+        return false;
+    }
+
+    let mut answer = String::new();
+    if input.read_line(&mut answer).is_err() {
+        return false;
+    }
+    matches!(answer.trim().to_lowercase().as_str(), "y" | "yes")
+}
+
+// Rename a document and its export
+fn cli_rename(feed: &Feed, old: &str, new: &str) -> Result<(), String> {
+    let old_md_path = feed.document_path(old);
+    let new_md_path = feed.document_path(new);
+    let old_html_path = feed.export_path(old);
+    let new_html_path = feed.export_path(new);
+
+    let content =
+        fs::read_to_string(&old_md_path).map_err(|_| "No documents with that name.".to_string())?;
+
+    for path in [&new_md_path, &new_html_path] {
+        if fs::metadata(path).is_ok() {
+            return Err(format!(
+                "Cannot rename '{old}' to '{new}': {} already exists.",
+                path.display()
+            ));
+        }
+    }
+
+    fs::rename(&old_md_path, &new_md_path)
+        .map_err(|err| format!("Failed to rename {}: {err}.", old_md_path.display()))?;
+
+    // Update the heading only if it is still the one `create` wrote
+    let heading = content.lines().find(|line| line.starts_with("# "));
+    if heading.map(str::trim_end) == Some(format!("# {old}").as_str()) {
+        if let Err(err) = fs::write(&new_md_path, replace_heading(&content, new)) {
+            eprintln!(
+                "Failed to update the heading of {}: {err}.",
+                new_md_path.display()
+            );
+        }
+    }
+
+    info!(feed, "Renamed source document '{old}' to '{new}'.");
+
+    if fs::metadata(&old_html_path).is_ok() {
+        fs::rename(&old_html_path, &new_html_path)
+            .map_err(|err| format!("Failed to rename {}: {err}.", old_html_path.display()))?;
+        info!(feed, "Renamed exported document '{old}' to '{new}'.");
+    }
+    Ok(())
+}
+
+// Copy a document to a new name as the start of a new post
+fn cli_copy(feed: &Feed, source: &str, destination: &str) -> Result<(), String> {
+    let source_path = feed.document_path(source);
+    let destination_path = feed.document_path(destination);
+
+    let content =
+        fs::read_to_string(&source_path).map_err(|_| "No documents with that name.".to_string())?;
+
+    if fs::metadata(&destination_path).is_ok() {
+        return Err(format!(
+            "Document already exists: {}.",
+            destination_path.display()
+        ));
+    }
+
+    // The copy is a new post, so it should not inherit the original's date
+    let content = replace_heading(&frontmatter::strip_date(&content), destination);
+    fs::write(&destination_path, content).map_err(|err| {
+        format!(
+            "Failed to create file {}: {err}.",
+            destination_path.display()
+        )
+    })?;
+
+    info!(feed, "Copied '{source}' to {}.", destination_path.display());
+    Ok(())
+}
+
+// Replace the text of a document's first top-level heading
+fn replace_heading(content: &str, heading: &str) -> String {
+    let mut replaced = false;
+    content
+        .split_inclusive('\n')
+        .map(|line| {
+            if replaced || !line.starts_with("# ") {
+                return line.to_string();
+            }
+            replaced = true;
+            let ending = &line[line.trim_end().len()..];
+            format!("# {heading}{ending}")
+        })
+        .collect()
+}
+
+// Edit a requested document
+fn cli_edit(feed: &Feed, filename: &str) -> Result<(), String> {
+    let file_path = feed.document_path(filename);
+
+    if fs::read(&file_path).is_err() {
+        return Err("No documents with that name.".to_string());
+    }
+
+    let editor = editor(|name| env::var(name).ok()).ok_or_else(|| {
+        "No editor found. Set the VISUAL or EDITOR environment variable.".to_string()
+    })?;
+
+    // Editors may be configured with arguments, such as `code --wait`
+    let mut words = editor.split_whitespace();
+    let Some(program) = words.next() else {
+        return Ok(());
+    };
+    let status = Command::new(program).args(words).arg(&file_path).status();
+    match status {
+        Ok(status) if !status.success() => Err(format!("{editor} exited with {status}.")),
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("Failed to launch {editor}: {e}.")),
+    }
+}
+
+// Choose an editor from the environment, falling back to one the platform is likely to have
+fn editor(var: impl Fn(&str) -> Option<String>) -> Option<String> {
+    let configured = ["VISUAL", "EDITOR"]
+        .into_iter()
+        .filter_map(&var)
+        .find(|editor| !editor.trim().is_empty());
+    if configured.is_some() {
+        return configured;
+    }
+
+    if cfg!(target_os = "windows") {
+        return Some("notepad".to_string());
+    }
+    let path = var("PATH").unwrap_or_default();
+    ["nano", "vi"]
+        .into_iter()
+        .find(|editor| env::split_paths(&path).any(|dir| dir.join(editor).is_file()))
+        .map(str::to_string)
+}
+
+// Open an exported document in the default browser
+fn cli_open(feed: &Feed, document: &str, yes: bool) -> Result<(), String> {
+    open_with(feed, document, yes, |command| command.status())
+}
+
+// Open an exported document, running the opener through the given launcher
+fn open_with(
+    feed: &Feed,
+    document: &str,
+    yes: bool,
+    launch: impl FnOnce(&mut Command) -> io::Result<ExitStatus>,
+) -> Result<(), String> {
+    let html_file_path = feed.export_path(document);
+
+    if fs::metadata(&html_file_path).is_err() {
+        if fs::metadata(feed.document_path(document)).is_err() {
+            return Err("No documents with that name.".to_string());
+        }
+        if !yes
+            && !confirm(&format!(
+                "'{document}' has not been exported. Export it now?"
+            ))
+        {
+            return Ok(());
+        }
+        cli_export(feed, document, false)?;
+    }
+
+    match launch(&mut opener(&html_file_path)) {
+        Ok(status) if status.success() => {
+            info!(feed, "Opened {}.", html_file_path.display());
+            Ok(())
+        }
+        Ok(status) => Err(format!(
+            "Failed to open {}: opener exited with {status}.",
+            html_file_path.display()
+        )),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Err(format!(
+            "No program was found to open {}. Open it in your browser manually.",
+            html_file_path.display()
+        )),
+        Err(e) => Err(format!("Failed to open {}: {e}.", html_file_path.display())),
+    }
+}
+
+// Build the command that opens a file with the platform's default application
+fn opener(path: &Path) -> Command {
+    let mut command = if cfg!(target_os = "windows") {
+        let mut command = Command::new("cmd");
+        command.args(["/C", "start", ""]);
+        command
+    } else if cfg!(target_os = "macos") {
+        Command::new("open")
+    } else {
+        Command::new("xdg-open")
+    };
+    command.arg(path);
+    command
+}
+
+// Generate a HTML version of the input document
+fn cli_export(feed: &Feed, document: &str, force: bool) -> Result<(), String> {
+    // Exports may have been edited by hand, so they are only replaced when asked
+    let html_file_path = feed.export_path(document);
+    if !force && html_file_path.exists() {
+        return Err(format!(
+            "{} already exists. Use --force to overwrite it.",
+            html_file_path.display()
+        ));
+    }
+
+    let conf = export_conf(feed)?;
+    export_document(feed, document, &conf)?;
+    info!(feed, "Successfully exported {document}.");
+    Ok(())
+}
+
+// Generate a HTML version of every document
+fn cli_export_all(feed: &Feed, include_drafts: bool, force: bool) -> Result<(), String> {
+    let conf = export_conf(feed)?;
+    let documents =
+        document_names(feed).map_err(|e| format!("Failed to read the documents folder: {e}."))?;
+
+    let mut failed = 0;
+    let mut drafts = 0;
+    let mut existing = 0;
+    for document in &documents {
+        if !include_drafts && read_document(feed, document).0.draft.unwrap_or(false) {
+            info!(feed, "Skipped draft {document}.");
+            drafts += 1;
+            continue;
+        }
+        if !force && feed.export_path(document).exists() {
+            info!(feed, "Skipped {document}, which has already been exported.");
+            existing += 1;
+            continue;
+        }
+
+        match export_document(feed, document, &conf) {
+            Ok(()) => info!(feed, "Exported {document}."),
+            Err(e) => {
+                eprintln!("{e}");
+                failed += 1;
+            }
+        }
+    }
+
+    info!(feed,
+        "Exported {} documents, {failed} failed, {drafts} drafts skipped, {existing} existing skipped.",
+        documents.len() - failed - drafts - existing
+    );
+    if existing > 0 {
+        info!(feed, "Use --force to overwrite existing exports.");
+    }
+
+    if failed > 0 {
+        return Err(format!("{failed} documents failed to export."));
+    }
+    Ok(())
+}
+
+// Re-export documents whenever they or the configuration change
+fn cli_watch(feed: &Feed, include_drafts: bool) -> Result<(), String> {
+    let (sender, receiver) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(sender)
+        .map_err(|e| format!("Failed to start watching for changes: {e}"))?;
+
+    // The working directory is watched rather than conf.toml itself, as editors often replace files when saving
+    for dir in [feed.path("documents"), feed.root.clone()] {
+        watcher
+            .watch(&dir, RecursiveMode::NonRecursive)
+            .map_err(|e| format!("Failed to watch {}: {e}", dir.display()))?;
+    }
+
+    info!(feed, "Watching for changes. Press Ctrl-C to stop.");
+
+    while let Ok(event) = receiver.recv() {
+        let mut changed = BTreeSet::new();
+        let mut collect = |event: notify::Result<notify::Event>| match event {
+            Ok(event) if !matches!(event.kind, EventKind::Access(_)) => changed.extend(event.paths),
+            Ok(_) => {}
+            Err(e) => eprintln!("Error watching for changes: {e}"),
+        };
+
+        // Editors tend to save in several steps, so gather events until things settle
+        collect(event);
+        while let Ok(event) = receiver.recv_timeout(Duration::from_millis(200)) {
+            collect(event);
+        }
+
+        rebuild(feed, &changed, include_drafts);
+    }
+    Ok(())
+}
+
+// Export whatever is affected by a set of changed paths
+fn rebuild(feed: &Feed, changed: &BTreeSet<PathBuf>, include_drafts: bool) {
+    let conf_changed = changed
+        .iter()
+        .any(|path| path.file_name().is_some_and(|name| name == "conf.toml"));
+    let documents = changed
+        .iter()
+        .filter(|path| path.extension().is_some_and(|extension| extension == "md"))
+        .filter(|path| {
+            path.parent()
+                .and_then(Path::file_name)
+                .is_some_and(|dir| dir == "documents")
+        })
+        .filter(|path| path.is_file())
+        .filter_map(|path| document_name(path))
+        .collect::<Vec<_>>();
+
+    if !conf_changed && documents.is_empty() {
+        return;
+    }
+
+    for path in changed {
+        detail!(feed, "Changed: {}", path.display());
+    }
+
+    info!(feed, "[{}] Rebuilding...", Local::now().format("%H:%M:%S"));
+
+    // A failed rebuild is reported, but the watch carries on so the next save can fix it
+    if conf_changed {
+        if let Err(e) = cli_export_all(feed, include_drafts, true) {
+            eprintln!("{e}");
+        }
+        return;
+    }
+
+    let conf = match export_conf(feed) {
+        Ok(conf) => conf,
+        Err(e) => {
+            eprintln!("{e}");
+            return;
+        }
+    };
+
+    for document in documents {
+        match export_document(feed, &document, &conf) {
+            Ok(()) => info!(feed, "Exported {document}."),
+            Err(e) => eprintln!("{e}"),
+        }
+    }
+}
+
+// Serve the export directory over HTTP for previewing
+fn cli_serve(feed: &Feed, port: Option<&str>) -> Result<(), String> {
+    let port = match port.map(str::parse::<u16>) {
+        None => 8080,
+        Some(Ok(port)) => port,
+        Some(Err(e)) => return Err(format!("Invalid port: {e}")),
+    };
+
+    let server = Server::http(("127.0.0.1", port))
+        .map_err(|e| format!("Failed to start the server on port {port}: {e}"))?;
+
+    info!(
+        feed,
+        "Serving {} at http://127.0.0.1:{port}/. Press Ctrl-C to stop.",
+        feed.path("export").display()
+    );
+
+    for request in server.incoming_requests() {
+        let response = export_response(feed, request.url());
+        if let Err(e) = request.respond(response) {
+            eprintln!("Failed to respond to request: {e}");
+        }
+    }
+    Ok(())
+}
+
+// Build the response for a requested URL from the export directory
+fn export_response(feed: &Feed, url: &str) -> Response<Cursor<Vec<u8>>> {
+    let path = url.split(['?', '#']).next().unwrap_or_default();
+
+    // Decode the path before checking it, so an encoded `..` is caught like any other
+    let Ok(path) = percent_decode_str(path).decode_utf8() else {
+        return not_found(feed);
+    };
+
+    // Never serve anything outside of the export directory
+    let relative = Path::new(path.trim_start_matches('/'));
+    if !relative
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)))
+    {
+        return not_found(feed);
+    }
+
+    let export = feed.path("export");
+    let mut file_path = export.join(relative);
+    if file_path.is_dir() {
+        file_path.push("index.html");
+    }
+
+    // Links inside the export directory could still lead out of it
+    let inside_export = match (file_path.canonicalize(), export.canonicalize()) {
+        (Ok(file), Ok(export)) => file.starts_with(export),
+        _ => false,
+    };
+    if !inside_export {
+        return not_found(feed);
+    }
+
+    match fs::read(&file_path) {
+        Ok(content) => {
+            let content_type = content_type(&file_path.to_string_lossy());
+            Response::from_data(content).with_header(content_type_header(&content_type))
+        }
+        Err(_) => not_found(feed),
+    }
+}
+
+// Respond with the site's 404.html if it has one, or a plain message otherwise
+fn not_found(feed: &Feed) -> Response<Cursor<Vec<u8>>> {
+    let response = match fs::read(feed.path("export/404.html")) {
+        Ok(content) => Response::from_data(content)
+            .with_header(content_type_header("text/html; charset=utf-8")),
+        Err(_) => Response::from_string("404 Not Found"),
+    };
+    response.with_status_code(404)
+}
+
+// Build a Content-Type header
+fn content_type_header(content_type: &str) -> Header {
+    Header::from_bytes("Content-Type", content_type).unwrap()
+}
+
+// Load the shared configuration used for exports
+fn export_conf(feed: &Feed) -> Result<Conf, String> {
+    let conf_path = feed.path("conf.toml");
+    let content = fs::read_to_string(&conf_path)
+        .map_err(|e| format!("{e}\nYou must manually create a conf.toml file for your feed."))?;
+
+    // The error shows the offending line with a caret under the column
+    let conf = toml::from_str::<Conf>(&content)
+        .map_err(|e| format!("Error parsing configuration file: {e}"))?;
+
+    let mut conf =
+        resolve_conf(conf, &conf_path).map_err(|e| format!("Error in configuration file: {e}"))?;
+    if let Some(main) = &mut conf.main {
+        resolve_content_files(feed, &mut main.block);
+    }
+    Ok(conf)
+}
+
+// Make the files blocks are read from relative to the feed, as conf.toml is
+fn resolve_content_files(feed: &Feed, blocks: &mut [Object]) {
+    for block in blocks {
+        if let Some(file) = &mut block.content_file {
+            *file = feed
+                .path(file.trim_start_matches("./"))
+                .to_string_lossy()
+                .into_owned();
+        }
+        if let Some(children) = &mut block.children {
+            resolve_content_files(feed, children);
+        }
+    }
+}
+
+// Render a document into the export directory using the shared configuration
+fn export_document(feed: &Feed, document: &str, conf: &Conf) -> Result<(), String> {
+    let md_file_path = feed.document_path(document);
+    if fs::metadata(&md_file_path).is_err() {
+        return Err(format!(
+            "Input file '{document}' does not exist. Please create it first."
+        ));
+    }
+
+    let content = fs::read_to_string(&md_file_path)
+        .map_err(|e| format!("Failed to read {}: {e}.", md_file_path.display()))?;
+    let (front_matter, body) = frontmatter::split(&content).map_err(|e| {
+        format!(
+            "Error parsing front matter of {}: {e}",
+            md_file_path.display()
+        )
+    })?;
+
+    detail!(
+        feed,
+        "Rendering {} to {}.",
+        md_file_path.display(),
+        feed.export_path(document).display()
+    );
+    let md_object = Object {
+        format: Some("md".to_string()),
+        content: Some(body.to_string()),
+        ..Default::default()
+    };
+
+    let preview = preview(document, &front_matter, body, conf);
+    let structured_data = article_data(&preview, &front_matter, &md_file_path, conf);
+
+    // Front matter takes precedence over the site-wide configuration
+    let mut toml = conf.clone();
+    toml.page = Some(format!("{document}.html"));
+    toml.structured_data = structured_data;
+    toml.document_dirs = Some(feed.document_dirs());
+    toml.title = front_matter.title.or(toml.title);
+    toml.subtitle = front_matter.description.or(toml.subtitle);
+    toml.lang = front_matter.lang.or(toml.lang);
+    toml.toc = front_matter.toc.or(toml.toc);
+
+    let meta = toml.meta.get_or_insert_with(Vec::new);
+    meta.extend(opengraph_tags(&preview));
+    meta.extend(twitter_tags(&preview, conf.twitter_handle.as_deref()));
+
+    // The document sits in its own article, which is all that feeds take from the exported page
+    let article = Object {
+        format: Some("article".to_string()),
+        children: Some(vec![md_object]),
+        ..Default::default()
+    };
+    let mut document_blocks = vec![article];
+    if toml.reading_time.unwrap_or(false) {
+        let stats = text_stats(body, front_matter.draft.unwrap_or(false));
+        let reading_time = stats.reading_time();
+        document_blocks.insert(
+            0,
+            Object {
+                format: Some("html".to_string()),
+                content: Some(format!(
+                    "<span class=\"reading-time\">{reading_time}</span>"
+                )),
+                ..Default::default()
+            },
+        );
+
+        let meta = toml.meta.get_or_insert_with(Vec::new);
+        meta.push(MetaTag {
+            name: Some("twitter:label1".to_string()),
+            content: "Reading time".to_string(),
+            ..Default::default()
+        });
+        meta.push(MetaTag {
+            name: Some("twitter:data1".to_string()),
+            content: reading_time,
+            ..Default::default()
+        });
+    }
+
+    let main = toml.main.get_or_insert_with(Main::default);
+    // A block that already includes this document is replaced rather than repeated
+    let position = main.block.iter().position(|obj| {
+        obj.format.as_deref() == Some("document")
+            || obj
+                .content_file
+                .as_deref()
+                .is_some_and(|file| Path::new(file) == md_file_path)
+    });
+
+    if let Some(pos) = position {
+        main.block.splice(pos..=pos, document_blocks);
+    } else {
+        main.block.extend(document_blocks);
+    }
+
+    let html = toml
+        .to_document()
+        .map_err(|err| format!("Failed to export {document}: {err}."))?;
+    write_atomic(feed.export_path(document), &with_trailing_newline(&html))
+        .map_err(|err| format!("Failed to export {document}: {err}."))
+}
+
+// What a shared link to a document shows, resolved once so every kind of preview agrees
+struct Preview {
+    title: Option<String>,
+    description: Option<String>,
+    url: Option<String>,
+    image: Option<String>,
+}
+
+// Work out a document's preview from its front matter, falling back to its heading and the site
+fn preview(document: &str, front_matter: &FrontMatter, body: &str, conf: &Conf) -> Preview {
+    let heading = body
+        .lines()
+        .find_map(|line| line.strip_prefix("# "))
+        .map(|heading| plain_text(heading).trim().to_string())
+        .filter(|heading| !heading.is_empty());
+
+    // Previews are fetched from elsewhere, so links only make sense when the site's address is known
+    let absolute = |path: &str| {
+        if path.contains("://") {
+            Some(path.to_string())
+        } else {
+            conf.base_url.as_ref().map(|_| conf.absolute(path))
+        }
+    };
+
+    Preview {
+        title: front_matter.title.clone().or(heading),
+        description: front_matter
+            .description
+            .clone()
+            .or_else(|| conf.subtitle.clone()),
+        url: absolute(&format!("{document}.html")),
+        image: front_matter.image.as_deref().and_then(absolute),
+    }
+}
+
+// OpenGraph tags for a document, leaving out any the preview has nothing for
+fn opengraph_tags(preview: &Preview) -> Vec<MetaTag> {
+    let properties = [
+        ("og:type", Some("article")),
+        ("og:title", preview.title.as_deref()),
+        ("og:description", preview.description.as_deref()),
+        ("og:url", preview.url.as_deref()),
+        ("og:image", preview.image.as_deref()),
+    ];
+    properties
+        .into_iter()
+        .filter_map(|(property, content)| {
+            Some(MetaTag {
+                property: Some(property.to_string()),
+                content: content?.to_string(),
+                ..Default::default()
+            })
+        })
+        .collect()
+}
+
+// Twitter Card tags for a document, showing a large image whenever the preview has one
+fn twitter_tags(preview: &Preview, handle: Option<&str>) -> Vec<MetaTag> {
+    let card = if preview.image.is_some() {
+        "summary_large_image"
+    } else {
+        "summary"
+    };
+    let site = handle.map(|handle| format!("@{}", handle.trim_start_matches('@')));
+    let names = [
+        ("twitter:card", Some(card)),
+        ("twitter:title", preview.title.as_deref()),
+        ("twitter:description", preview.description.as_deref()),
+        ("twitter:image", preview.image.as_deref()),
+        ("twitter:site", site.as_deref()),
+    ];
+    names
+        .into_iter()
+        .filter_map(|(name, content)| {
+            Some(MetaTag {
+                name: Some(name.to_string()),
+                content: content?.to_string(),
+                ..Default::default()
+            })
+        })
+        .collect()
+}
+
+// Schema.org Article data for a document, which search engines need a headline and date for
+fn article_data(
+    preview: &Preview,
+    front_matter: &FrontMatter,
+    md_file_path: &Path,
+    conf: &Conf,
+) -> Option<String> {
+    let headline = preview.title.as_deref()?;
+    let published = front_matter.date.as_deref()?;
+
+    let mut article = serde_json::json!({
+        "@context": "https://schema.org",
+        "@type": "Article",
+        "headline": headline,
+        "datePublished": published,
+    });
+    if let Ok(modified) = fs::metadata(md_file_path).and_then(|metadata| metadata.modified()) {
+        article["dateModified"] = DateTime::<Utc>::from(modified).to_rfc3339().into();
+    }
+    if let Some(description) = &preview.description {
+        article["description"] = description.as_str().into();
+    }
+    if let Some(url) = &preview.url {
+        article["url"] = url.as_str().into();
+    }
+    if let Some(image) = &preview.image {
+        article["image"] = image.as_str().into();
+    }
+    if let Some(author) = front_matter.author.as_deref().or(conf.author.as_deref()) {
+        let name = split_author(author).map_or(author.trim(), |(_, name)| name);
+        article["author"] = serde_json::json!({ "@type": "Person", "name": name });
+    }
+    if let Some(title) = &conf.title {
+        let mut publisher = serde_json::json!({ "@type": "Organization", "name": title });
+        if let Some(base_url) = &conf.base_url {
+            publisher["url"] = base_url.as_str().into();
+        }
+        article["publisher"] = publisher;
+    }
+
+    Some(article.to_string())
+}
+
+// Search documents
+fn cli_search(feed: &Feed, keyword: &str, case_sensitive: bool, regex: bool) -> Result<(), String> {
+    let results = search_documents(feed, keyword, case_sensitive, regex)?;
+    if results.is_empty() {
+        println!("No results found for '{keyword}'.");
+    }
+    for result in results {
+        println!("{result}");
+    }
+    Ok(())
+}
+
+// Documents whose name or contents match, each printed once
+fn search_documents(
+    feed: &Feed,
+    keyword: &str,
+    case_sensitive: bool,
+    regex: bool,
+) -> Result<Vec<String>, String> {
+    // Plain queries are escaped so both kinds of search share one matcher
+    let pattern = if regex {
+        keyword.to_string()
+    } else {
+        regex::escape(keyword)
+    };
+    let matcher = RegexBuilder::new(&pattern)
+        .case_insensitive(!case_sensitive)
+        .build()
+        .map_err(|e| format!("Invalid regular expression '{keyword}': {e}"))?;
+
+    let entries = document_names(feed).map_err(|e| {
+        format!(
+            "Failed to read the documents folder: {e}.\nRun `adduce feed establish` to create it."
+        )
+    })?;
+
+    let mut results = Vec::new();
+
+    for entry in entries {
+        if matcher.is_match(&entry) {
+            results.push(entry);
+            continue;
+        }
+
+        // Otherwise report the first line of the document that matches
+        let content = fs::read_to_string(feed.document_path(&entry)).unwrap_or_default();
+        if let Some((number, line)) = content
+            .lines()
+            .enumerate()
+            .find(|(_, line)| matcher.is_match(line))
+        {
+            results.push(format!("{entry}:{}: {}", number + 1, line.trim()));
+        }
+    }
+
+    Ok(results)
+}
+
+// List documents and whether each has been exported
+fn cli_list(feed: &Feed, exported_only: bool) -> Result<(), String> {
+    for line in document_listing(feed, exported_only)? {
+        println!("{line}");
+    }
+    Ok(())
+}
+
+// The lines printed by `list`
+fn document_listing(feed: &Feed, exported_only: bool) -> Result<Vec<String>, String> {
+    let documents = document_names(feed).map_err(|_| {
+        "The documents folder does not exist. Please run `adduce feed establish` to create the necessary file structure.".to_string()
+    })?;
+
+    if documents.is_empty() {
+        return Ok(vec!["No documents yet.".to_string()]);
+    }
+
+    let mut lines = Vec::new();
+    for document in documents {
+        let exported = fs::metadata(feed.export_path(&document)).is_ok();
+        if exported {
+            lines.push(format!("{document} (exported)"));
+        } else if !exported_only {
+            lines.push(document);
+        }
+    }
+    Ok(lines)
+}
+
+// Average reading speed used to estimate reading time
+const WORDS_PER_MINUTE: usize = 200;
+
+// Word and character counts of a document's text
+struct Stats {
+    words: usize,
+    characters: usize,
+    draft: bool,
+}
+
+impl Stats {
+    // Estimated reading time in minutes, rounded up
+    fn reading_minutes(&self) -> usize {
+        self.words.div_ceil(WORDS_PER_MINUTE)
+    }
+
+    // Reading time as shown on an exported page
+    fn reading_time(&self) -> String {
+        if self.draft || self.words < WORDS_PER_MINUTE {
+            "less than a minute".to_string()
+        } else {
+            format!("{} min read", self.reading_minutes())
+        }
+    }
+}
+
+// Count the words in a document, ignoring its front matter and Markdown syntax
+fn document_stats(feed: &Feed, document: &str) -> Result<Stats, String> {
+    let md_file_path = feed.document_path(document);
+    let content = fs::read_to_string(&md_file_path)
+        .map_err(|e| format!("Failed to read {}: {e}.", md_file_path.display()))?;
+    let (front_matter, body) = frontmatter::split(&content).map_err(|e| {
+        format!(
+            "Error parsing front matter of {}: {e}",
+            md_file_path.display()
+        )
+    })?;
+
+    Ok(text_stats(body, front_matter.draft.unwrap_or(false)))
+}
+
+// Count the words and characters in the text of a Markdown body
+fn text_stats(body: &str, draft: bool) -> Stats {
+    let text = plain_text(body);
+    Stats {
+        words: text.split_whitespace().count(),
+        characters: text.chars().filter(|c| !c.is_whitespace()).count(),
+        draft,
+    }
+}
+
+// Print statistics for a document
+fn cli_stats(feed: &Feed, document: &str) -> Result<(), String> {
+    let stats = document_stats(feed, document)?;
+    println!("Words: {}", stats.words);
+    println!("Characters: {}", stats.characters);
+    println!("Reading time: {} min", stats.reading_minutes());
+    if stats.draft {
+        println!("This document is a draft.");
+    }
+    Ok(())
+}
+
+// Print a table of statistics for every document
+fn cli_stats_all(feed: &Feed) -> Result<(), String> {
+    let documents =
+        document_names(feed).map_err(|e| format!("Failed to read the documents folder: {e}."))?;
+
+    let width = documents.iter().map(|d| d.len()).max().unwrap_or(0).max(8);
+    println!(
+        "{:width$}  {:>8}  {:>10}  {:>8}",
+        "Document", "Words", "Characters", "Minutes"
+    );
+
+    let mut total = Stats {
+        words: 0,
+        characters: 0,
+        draft: false,
+    };
+    for document in &documents {
+        match document_stats(feed, document) {
+            Ok(stats) => {
+                let draft = if stats.draft { "  (draft)" } else { "" };
+                println!(
+                    "{document:width$}  {:>8}  {:>10}  {:>8}{draft}",
+                    stats.words,
+                    stats.characters,
+                    stats.reading_minutes()
+                );
+                total.words += stats.words;
+                total.characters += stats.characters;
+            }
+            Err(e) => eprintln!("{e}"),
+        }
+    }
+
+    println!(
+        "{:width$}  {:>8}  {:>10}  {:>8}",
+        "Total",
+        total.words,
+        total.characters,
+        total.reading_minutes()
+    );
+    Ok(())
+}
+
+// Get the names of every document, sorted alphabetically
+fn document_names(feed: &Feed) -> std::io::Result<Vec<String>> {
+    let mut documents = BTreeSet::new();
+    for (i, dir) in feed.document_dirs().iter().enumerate() {
+        // A linked folder that has gone missing is skipped, but documents/ itself must exist
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) if i == 0 => return Err(e),
+            Err(_) => continue,
+        };
+        documents.extend(
+            entries
+                .filter_map(|entry| entry.ok().map(|e| e.path()))
+                .filter(|path| !is_hidden(path))
+                .filter(|path| path.extension().is_some_and(|extension| extension == "md"))
+                .filter(|path| is_document_file(path))
+                .filter_map(|path| document_name(&path)),
+        );
+    }
+    Ok(documents.into_iter().collect())
+}
+
+// Symbolic links to documents are followed, while folders are only read through linked_dirs
+fn is_document_file(path: &Path) -> bool {
+    match fs::metadata(path) {
+        Ok(metadata) => metadata.is_file(),
+        Err(_) if path.is_symlink() => {
+            eprintln!("Skipping {}, a broken symbolic link.", path.display());
+            false
+        }
+        Err(_) => false,
+    }
+}
+
+// Name a document after its file, skipping files whose names cannot be used as document names
+fn document_name(path: &Path) -> Option<String> {
+    let stem = path.file_stem()?;
+    match stem.to_str() {
+        Some(name) => Some(name.to_string()),
+        None => {
+            eprintln!(
+                "Skipping {}: its name is not valid UTF-8.",
+                path.to_string_lossy()
+            );
+            None
+        }
+    }
+}
+
+// Check that a document name cannot reach outside of the documents and export folders
+fn is_valid_document_name(name: &str) -> bool {
+    !name.is_empty() && !name.contains(['/', '\\', '\0']) && name != "." && name != ".."
+}
+
+// Check for dotfiles, such as editor swap files and .DS_Store
+fn is_hidden(path: &Path) -> bool {
+    path.file_name()
+        .is_some_and(|name| name.to_string_lossy().starts_with('.'))
+}
+
+// Generate an Atom feed
+fn cli_atom(feed: &Feed, include_drafts: bool) -> Result<(), String> {
+    let conf = feed_conf(feed, "Atom")?;
+    let output_file = atom_output_file(&conf)?.to_string();
+
+    let feed_id = conf.id.clone().unwrap();
+    let default_author = conf.author.as_deref().map(author_person);
+    let pages = collect_pages(feed, &conf, include_drafts)
+        .map_err(|e| format!("Atom feed not generated. {e}"))?;
+    let entries = pages
+        .into_iter()
+        .map(|page| {
+            // Derive a stable ID from the feed ID and the document's name
+            let id = format!("{}/{}", feed_id.trim_end_matches('/'), page.slug);
+
+            // Documents without an author of their own are credited to the site's author
+            let authors = page
+                .front_matter
+                .author
+                .as_deref()
+                .map(author_person)
+                .or_else(|| default_author.clone())
+                .into_iter()
+                .collect::<Vec<_>>();
+
+            let categories = page
+                .front_matter
+                .categories
+                .clone()
+                .unwrap_or_default()
+                .into_iter()
+                .map(|term| CategoryBuilder::default().term(term).build())
+                .collect::<Vec<_>>();
+
+            let mut links = page
+                .front_matter
+                .enclosure
+                .map(|enclosure| enclosure_link(feed, enclosure))
+                .into_iter()
+                .collect::<Vec<_>>();
+            if conf.base_url.is_some() {
+                links.push(
+                    LinkBuilder::default()
+                        .href(conf.absolute(&format!("{}.html", page.slug)))
+                        .rel("alternate".to_string())
+                        .mime_type(Some("text/html".to_string()))
+                        .build(),
+                );
+            }
+
+            EntryBuilder::default()
+                .title(Text::plain(page.title))
+                .summary(page.summary.map(Text::plain))
+                .id(id)
+                .authors(authors)
+                .categories(categories)
+                .links(links)
+                .published(page.published.map(|published| published.fixed_offset()))
+                .updated(page.updated)
+                .content(
+                    ContentBuilder::default()
+                        .value(page.content)
+                        .content_type(Some("html".to_string()))
+                        .build(),
+                )
+                .build()
+        })
+        .collect::<Vec<_>>();
+
+    let generator = GeneratorBuilder::default()
+        .value("Adduce".to_string())
+        .uri("http://adduce.vale.rocks".to_string())
+        .version(env!("CARGO_PKG_VERSION").to_string())
+        .build();
+
+    let categories = conf
+        .categories
+        .unwrap_or_default()
+        .into_iter()
+        .map(|term| CategoryBuilder::default().term(term).build())
+        .collect::<Vec<_>>();
+
+    // Link the feed to where it is published, defaulting to its output file under the base URL
+    let links = conf
+        .feed_url
+        .clone()
+        .or_else(|| {
+            conf.base
+                .as_ref()
+                .map(|base| format!("{}/{output_file}", base.trim_end_matches('/')))
+        })
+        .map(|href| {
+            LinkBuilder::default()
+                .href(href)
+                .rel("self")
+                .mime_type(Some("application/atom+xml".to_string()))
+                .build()
+        })
+        .into_iter()
+        .collect::<Vec<_>>();
+
+    // The feed was last updated when its newest entry was
+    let updated = entries
+        .iter()
+        .map(|entry| entry.updated)
+        .max()
+        .unwrap_or_else(|| Utc::now().fixed_offset());
+
+    let atom = FeedBuilder::default()
+        .title(Text::plain(conf.title.unwrap()))
+        .id(feed_id)
+        .updated(updated)
+        .authors(default_author.into_iter().collect::<Vec<_>>())
+        .categories(categories)
+        .generator(generator)
+        .icon(conf.icon)
+        .links(links)
+        .logo(conf.logo)
+        .rights(conf.rights.map(Text::plain))
+        .entries(entries)
+        .subtitle(conf.subtitle.map(Text::plain))
+        .base(conf.base)
+        .lang(conf.lang)
+        .build();
+
+    let output_path = feed.path("export").join(&output_file);
+    if let Some(parent) = output_path.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create {}: {e}", parent.display()))?;
+    }
+
+    write_atomic(&output_path, &with_trailing_newline(&atom.to_string()))
+        .map_err(|e| format!("Failed to write Atom feed: {e}"))?;
+    info!(feed, "Atom feed generated successfully.");
+    Ok(())
+}
+
+// Generate a JSON feed
+fn cli_json(feed: &Feed, include_drafts: bool) -> Result<(), String> {
+    let conf = feed_conf(feed, "JSON")?;
+
+    let feed_id = conf.id.clone().unwrap();
+    let default_author = conf.author.as_deref().map(author_person);
+    let pages = collect_pages(feed, &conf, include_drafts)
+        .map_err(|e| format!("JSON feed not generated. {e}"))?;
+    let items = pages
+        .into_iter()
+        .map(|page| JsonFeedItem {
+            authors: page
+                .front_matter
+                .author
+                .as_deref()
+                .map(author_person)
+                .or_else(|| default_author.clone())
+                .map(json_author)
+                .into_iter()
+                .collect(),
+            id: format!("{}/{}", feed_id.trim_end_matches('/'), page.slug),
+            url: conf
+                .base_url
+                .as_ref()
+                .map(|_| conf.absolute(&format!("{}.html", page.slug))),
+            title: page.title,
+            summary: page.summary,
+            content_html: page.content,
+            date_published: page.published.unwrap_or(page.updated).to_rfc3339(),
+            tags: page.front_matter.categories.unwrap_or_default(),
+        })
+        .collect();
+
+    let home_page_url = conf.base.clone();
+    let json_feed = JsonFeed {
+        version: "https://jsonfeed.org/version/1.1",
+        title: conf.title.unwrap(),
+        feed_url: home_page_url
+            .as_ref()
+            .map(|base| format!("{}/feed.json", base.trim_end_matches('/'))),
+        home_page_url,
+        description: conf.subtitle,
+        icon: conf.logo,
+        favicon: conf.icon,
+        language: conf.lang,
+        authors: default_author.map(json_author).into_iter().collect(),
+        items,
+    };
+
+    let json = serde_json::to_string_pretty(&json_feed)
+        .map_err(|e| format!("Failed to serialize JSON feed: {e}"))?;
+
+    write_atomic(feed.path("export/feed.json"), &with_trailing_newline(&json))
+        .map_err(|e| format!("Failed to write JSON feed: {e}"))?;
+    info!(feed, "JSON feed generated successfully.");
+    Ok(())
+}
+
+// Generate a sitemap listing every exported document
+fn cli_sitemap(feed: &Feed) -> Result<(), String> {
+    let conf = export_conf(feed)?;
+    if conf.base_url.is_none() {
+        return Err(
+            "Sitemap not generated. Set base_url in conf.toml to the address your site is published at."
+                .to_string(),
+        );
+    }
+
+    let mut paths = fs::read_dir(feed.path("export"))
+        .map_err(|e| format!("Failed to read export directory: {e}"))?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.is_file() && !is_hidden(path))
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "html")
+        })
+        .collect::<Vec<_>>();
+    paths.sort();
+
+    let mut urls = String::new();
+    for path in paths {
+        let Some(slug) = document_name(&path) else {
+            continue;
+        };
+        if read_document(feed, &slug).0.draft.unwrap_or(false) {
+            detail!(feed, "Leaving draft {slug} out of the sitemap.");
+            continue;
+        }
+        detail!(feed, "Adding {} to the sitemap.", path.display());
+
+        urls += &format!(
+            "  <url>\n    <loc>{}</loc>\n",
+            escape_html(&conf.absolute(&format!("{slug}.html")))
+        );
+        if let Ok(modified) = fs::metadata(&path).and_then(|metadata| metadata.modified()) {
+            let modified = DateTime::<Utc>::from(modified);
+            urls += &format!("    <lastmod>{}</lastmod>\n", modified.format("%Y-%m-%d"));
+        }
+        urls += "  </url>\n";
+    }
+
+    let sitemap = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n{urls}</urlset>\n"
+    );
+    write_atomic(feed.path("export/sitemap.xml"), &sitemap)
+        .map_err(|e| format!("Failed to write sitemap: {e}"))?;
+    info!(feed, "Sitemap generated successfully.");
+    Ok(())
+}
+
+// Generate a robots.txt, allowing every crawler everywhere unless rules are configured
+fn cli_robots(feed: &Feed) -> Result<(), String> {
+    let conf = export_conf(feed)?;
+    let rules = conf.robots.clone().unwrap_or_else(|| {
+        vec![RobotsRule {
+            user_agent: "*".to_string(),
+            ..Default::default()
+        }]
+    });
+
+    let mut robots = String::new();
+    for rule in rules {
+        if !robots.is_empty() {
+            robots.push('\n');
+        }
+        robots += &format!("User-agent: {}\n", rule.user_agent);
+        let allow = rule.allow.unwrap_or_default();
+        let disallow = rule.disallow.unwrap_or_default();
+
+        // A group needs at least one rule, and an empty Disallow permits everything
+        if allow.is_empty() && disallow.is_empty() {
+            robots += "Disallow:\n";
+        }
+        for path in allow {
+            robots += &format!("Allow: {path}\n");
+        }
+        for path in disallow {
+            robots += &format!("Disallow: {path}\n");
+        }
+    }
+
+    // Point crawlers at the sitemap, which is only generated when base_url is set
+    if conf.base_url.is_some() {
+        robots += &format!("\nSitemap: {}\n", conf.absolute("sitemap.xml"));
+    }
+
+    write_atomic(feed.path("export/robots.txt"), &robots)
+        .map_err(|e| format!("Failed to write robots.txt: {e}"))?;
+    info!(feed, "robots.txt generated successfully.");
+    Ok(())
+}
+
+// List the fields every feed requires that the configuration lacks
+fn missing_feed_fields(conf: &Conf) -> Vec<&'static str> {
+    let mut missing_fields = Vec::new();
+    if conf.title.is_none() {
+        missing_fields.push("title");
+    }
+    if conf.id.is_none() {
+        missing_fields.push("id");
+    }
+    missing_fields
+}
+
+// Get the path of the Atom feed relative to the export directory, which it must stay inside
+fn atom_output_file(conf: &Conf) -> Result<&str, String> {
+    let output_file = conf.output_file.as_deref().unwrap_or("feed.xml");
+    let path = Path::new(output_file);
+    let inside_export = path.components().next().is_some()
+        && path
+            .components()
+            .all(|component| matches!(component, Component::Normal(_) | Component::CurDir));
+    if !inside_export {
+        return Err(format!(
+            "Atom feed not generated. output_file '{output_file}' must be a relative path inside the export directory."
+        ));
+    }
+    Ok(output_file)
+}
+
+// Load the configuration for a feed, checking the fields every feed requires
+fn feed_conf(feed: &Feed, format: &str) -> Result<Conf, String> {
+    let conf_path = feed.path("conf.toml");
+    let conf_content = fs::read_to_string(&conf_path).map_err(|e| {
+        format!("Error reading configuration file: {e}\nNo configuration file found.")
+    })?;
+
+    let conf: Result<Conf, TomlError> = toml::from_str(&conf_content);
+    let conf = conf.map_err(|e| format!("Error parsing configuration file: {e}"))?;
+    let conf =
+        resolve_conf(conf, &conf_path).map_err(|e| format!("Error in configuration file: {e}"))?;
+
+    let missing_fields = missing_feed_fields(&conf);
+    if !missing_fields.is_empty() {
+        return Err(format!(
+            "{format} feed not generated. Missing required fields: {}.",
+            missing_fields.join(", ")
+        ));
+    }
+
+    Ok(conf)
+}
+
+// An exported page collected for inclusion in a feed
+struct Page {
+    slug: String,
+    title: String,
+    content: String,
+    published: Option<DateTime<Utc>>,
+    updated: DateTime<Utc>,
+    summary: Option<String>,
+    front_matter: FrontMatter,
+}
+
+// Read a front matter date, either a bare day such as 2024-05-01 or a full RFC 3339 timestamp
+fn parse_date(date: &str) -> Option<DateTime<Utc>> {
+    let date = date.trim();
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(date) {
+        return Some(datetime.with_timezone(&Utc));
+    }
+    NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .ok()
+        .and_then(|day| day.and_hms_opt(0, 0, 0))
+        .map(|midnight| midnight.and_utc())
+}
+
+// Number of characters in a generated summary
+const DEFAULT_EXCERPT_LENGTH: usize = 200;
+
+// Collect the exported pages to include in a feed, newest first
+fn collect_pages(feed: &Feed, conf: &Conf, include_drafts: bool) -> Result<Vec<Page>, String> {
+    let mut pages = Vec::new();
+    let mut unreadable = 0;
+
+    let entries = fs::read_dir(feed.path("export"))
+        .map_err(|e| format!("Failed to read export directory: {e}"))?;
+    for entry in entries {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                eprintln!("Skipping unreadable entry in export directory: {e}");
+                unreadable += 1;
+                continue;
+            }
+        };
+        let path = entry.path();
+
+        // Only exported pages belong in a feed, not the feeds themselves, other assets, or hidden files
+        if !path.is_file()
+            || is_hidden(&path)
+            || path.extension().is_none_or(|extension| extension != "html")
+        {
+            continue;
+        }
+
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(e) => {
+                eprintln!("Skipping {}: {e}", path.display());
+                unreadable += 1;
+                continue;
+            }
+        };
+
+        let Some(slug) = document_name(&path) else {
+            continue;
+        };
+        let (front_matter, source) = read_document(feed, &slug);
+
+        // Date the page by its front matter, so exporting again does not change it, or else by its export
+        let published = front_matter.date.as_deref().and_then(|date| {
+            let parsed = parse_date(date);
+            if parsed.is_none() {
+                eprintln!("Ignoring date '{date}' of {slug}: expected YYYY-MM-DD or RFC 3339.");
+            }
+            parsed
+        });
+        let updated = match published {
+            Some(published) => published,
+            None => match fs::metadata(&path).and_then(|metadata| metadata.modified()) {
+                Ok(modified) => DateTime::<Utc>::from(modified),
+                Err(e) => {
+                    eprintln!(
+                        "Could not read modification time of {}: {e}. Using the current time.",
+                        path.display()
+                    );
+                    Utc::now()
+                }
+            },
+        };
+        if !include_drafts && front_matter.draft.unwrap_or(false) {
+            detail!(feed, "Leaving draft {slug} out of the feed.");
+            continue;
+        }
+        detail!(feed, "Adding {} to the feed.", path.display());
+
+        // Title the entry the way its page is previewed, then by the page's og:title or file name
+        let title = preview(&slug, &front_matter, &source, conf)
+            .title
+            .or_else(|| meta_property(&content, "og:title"))
+            .unwrap_or_else(|| format!("{slug}.html"));
+
+        // Use the page's article as its content, or its body if there is no article
+        let body = element_contents(&content, "article")
+            .or_else(|| element_contents(&content, "body"))
+            .unwrap_or(&content)
+            .trim()
+            .to_string();
+
+        // An explicit description is preferred over one taken from the document
+        let summary = front_matter
+            .description
+            .clone()
+            .or_else(|| {
+                Some(excerpt(
+                    &source,
+                    conf.excerpt_length.unwrap_or(DEFAULT_EXCERPT_LENGTH),
+                ))
+            })
+            .filter(|summary| !summary.is_empty());
+
+        pages.push(Page {
+            front_matter,
+            summary,
+            slug,
+            title,
+            content: body,
+            published,
+            updated,
+        });
+    }
+
+    // Order pages newest first, using the document name to break ties
+    pages.sort_by(|a, b| b.updated.cmp(&a.updated).then_with(|| a.slug.cmp(&b.slug)));
+
+    // Keep only the most recent pages if a limit is configured
+    if let Some(max_items) = conf.max_items {
+        pages.truncate(max_items);
+    }
+
+    // A feed is still built around unreadable pages, unless none could be read at all
+    if pages.is_empty() && unreadable > 0 {
+        return Err("None of the exported pages could be read.".to_string());
+    }
+
+    // An empty feed is valid, but usually means the documents have not been exported yet
+    if pages.is_empty() {
+        eprintln!("Warning: No exported documents found; the feed will be empty.");
+    }
+    Ok(pages)
+}
+
+// Top level of a JSON Feed 1.1 document
+#[derive(Serialize)]
+struct JsonFeed {
+    version: &'static str,
+    title: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    home_page_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    feed_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    icon: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    favicon: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    language: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    authors: Vec<JsonFeedAuthor>,
+    items: Vec<JsonFeedItem>,
+}
+
+// A single item of a JSON Feed
+#[derive(Serialize)]
+struct JsonFeedItem {
+    id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    url: Option<String>,
+    title: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    summary: Option<String>,
+    content_html: String,
+    date_published: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    authors: Vec<JsonFeedAuthor>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
+}
+
+// An author of a JSON Feed or one of its items
+#[derive(Serialize)]
+struct JsonFeedAuthor {
+    name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    url: Option<String>,
+}
+
+// Describe an author for a JSON Feed, which links to an email address rather than holding it
+fn json_author(person: Person) -> JsonFeedAuthor {
+    JsonFeedAuthor {
+        name: person.name,
+        url: person.email.map(|email| format!("mailto:{email}")),
+    }
+}
+
+// Feeds only need a document's front matter and excerpt, so no more than this much of its body is read
+const OPENING_LENGTH: usize = 64 * 1024;
+
+// Read the front matter and opening of the source document behind an export, if there is one
+fn read_document(feed: &Feed, document: &str) -> (FrontMatter, String) {
+    let md_file_path = feed.document_path(document);
+    let Ok(content) = fs::File::open(&md_file_path).and_then(|file| {
+        frontmatter::read_opening(BufReader::new(file), EXCERPT_MARKER, OPENING_LENGTH)
+    }) else {
+        return (FrontMatter::default(), String::new());
+    };
+
+    match frontmatter::split(&content) {
+        Ok((front_matter, body)) => (front_matter, body.to_string()),
+        Err(e) => {
+            eprintln!(
+                "Error parsing front matter of {}: {e}",
+                md_file_path.display()
+            );
+            (FrontMatter::default(), String::new())
+        }
+    }
+}
+
+// Build an enclosure link, filling in a missing length or type from the local copy in assets/
+fn enclosure_link(feed: &Feed, enclosure: Enclosure) -> Link {
+    let file_name = enclosure.url.rsplit('/').next().unwrap_or_default();
+    let asset_path = feed.path("assets").join(file_name);
+
+    let length = enclosure
+        .length
+        .or_else(|| match fs::metadata(&asset_path) {
+            Ok(metadata) => Some(metadata.len()),
+            Err(e) => {
+                eprintln!("Could not determine the length of {}: {e}", enclosure.url);
+                None
+            }
+        });
+    let mime_type = enclosure
+        .mime_type
+        .unwrap_or_else(|| mime_type(file_name).to_string());
+
+    LinkBuilder::default()
+        .href(enclosure.url)
+        .rel("enclosure")
+        .mime_type(Some(mime_type))
+        .length(length.map(|length| length.to_string()))
+        .build()
+}
+
+// Build a person from an author in the `email (Name)` form
+fn author_person(author: &str) -> Person {
+    match split_author(author) {
+        Some((email, name)) => PersonBuilder::default()
+            .name(name)
+            .email(Some(email.to_string()))
+            .build(),
+        None => {
+            eprintln!("Author '{author}' is not in the form `email (Name)`. Using it as the name.");
+            PersonBuilder::default().name(author.trim()).build()
+        }
+    }
+}
+
+// Split an author in the `email (Name)` form into its email and name
+fn split_author(author: &str) -> Option<(&str, &str)> {
+    author
+        .trim()
+        .strip_suffix(')')
+        .and_then(|author| author.split_once(" ("))
+        .filter(|(email, _)| email.contains('@'))
+        .map(|(email, name)| (email.trim(), name.trim()))
+}
+
+// Find the content of a <meta property="..."> tag in a HTML document
+fn meta_property(html: &str, property: &str) -> Option<String> {
+    html.split("<meta").skip(1).find_map(|tag| {
+        let tag = &tag[..tag.find('>')?];
+        if attribute(tag, "property")? != property {
+            return None;
+        }
+        attribute(tag, "content")
+    })
+}
+
+// Find the inner HTML of the first element with the given tag name
+fn element_contents<'a>(html: &'a str, tag: &str) -> Option<&'a str> {
+    let open = html
+        .match_indices(&format!("<{tag}"))
+        .map(|(i, _)| i)
+        .find(|&i| matches!(html[i + tag.len() + 1..].chars().next(), Some('>' | ' ')))?;
+    let start = html[open..].find('>')? + open + 1;
+    let end = html[start..].find(&format!("</{tag}>"))? + start;
+    Some(&html[start..end])
+}
+
+// Read the value of an attribute from the inside of a HTML tag
+fn attribute(tag: &str, name: &str) -> Option<String> {
+    let start = tag.find(&format!("{name}="))? + name.len() + 1;
+    let value = &tag[start..];
+
+    // Minified pages may use single quotes or leave values unquoted
+    let value = match value.chars().next()? {
+        quote @ ('"' | '\'') => {
+            let value = &value[1..];
+            &value[..value.find(quote)?]
+        }
+        _ => &value[..value.find(char::is_whitespace).unwrap_or(value.len())],
+    };
+    Some(unescape_html(value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{cell::RefCell, rc::Rc};
+
+    // Run a test against a new feed directory holding the given files
+    fn in_feed(files: &[(&str, &str)], test: impl FnOnce(&Feed)) {
+        let dir = tempfile::tempdir().unwrap();
+        let feed = Feed::new(dir.path().to_path_buf());
+        for folder in ["documents", "export"] {
+            fs::create_dir_all(feed.path(folder)).unwrap();
+        }
+        for (path, content) in files {
+            let path = feed.path(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+        test(&feed);
+    }
+
+    const CONF: &str = r#"
+title = "Site"
+id = "https://example.com/"
+base_url = "https://example.com/"
+reading_time = true
+footer = "Site footer"
+nav = [{ label = "Home", href = "/" }]
+
+[[main.block]]
+format = "document"
+"#;
+
+    #[test]
+    fn feed_content_is_only_the_exported_article() {
+        let files = [
+            ("conf.toml", CONF),
+            ("documents/post.md", "# Post\n\nThe body.\n"),
+        ];
+        in_feed(&files, |feed| {
+            cli_export(feed, "post", false).unwrap();
+            let html = fs::read_to_string(feed.path("export/post.html")).unwrap();
+            assert!(html.contains("<article>"));
+
+            let conf = export_conf(feed).unwrap();
+            let pages = collect_pages(feed, &conf, false).unwrap();
+            assert_eq!(pages.len(), 1);
+            assert!(pages[0].content.contains("The body."));
+            for unwanted in ["<nav", "reading-time", "Site footer", "<article"] {
+                assert!(!pages[0].content.contains(unwanted), "{unwanted}");
+            }
+        });
+    }
+
+    #[test]
+    fn export_fails_on_an_unknown_format() {
+        let conf = format!("{CONF}\n[[main.block]]\nformat = \"1p\"\ncontent = \"x\"\n");
+        let files = [
+            ("conf.toml", conf.as_str()),
+            ("documents/post.md", "# Post\n"),
+        ];
+        in_feed(&files, |feed| {
+            let err = cli_export(feed, "post", false).unwrap_err();
+            assert!(err.contains("'1p'"), "{err}");
+            assert!(!feed.path("export/post.html").exists());
+        });
+    }
+
+    #[test]
+    fn feeds_carry_site_and_document_categories() {
+        let conf = format!("categories = [\"rust\", \"web\", \"notes\"]\n{CONF}");
+        let files = [
+            ("conf.toml", conf.as_str()),
+            (
+                "documents/post.md",
+                "---\ncategories: [release, tooling]\n---\n# Post\n",
+            ),
+        ];
+        in_feed(&files, |feed| {
+            cli_export(feed, "post", false).unwrap();
+            cli_atom(feed, false).unwrap();
+            cli_json(feed, false).unwrap();
+
+            let atom = fs::read_to_string(feed.path("export/feed.xml")).unwrap();
+            for term in ["rust", "web", "notes", "release", "tooling"] {
+                assert!(atom.contains(&format!("term=\"{term}\"")), "{term}");
+            }
+            let atom = atom_syndication::Feed::read_from(atom.as_bytes()).unwrap();
+            let terms = atom.entries()[0]
+                .categories()
+                .iter()
+                .map(|category| category.term())
+                .collect::<Vec<_>>();
+            assert_eq!(terms, ["release", "tooling"]);
+
+            let json = fs::read_to_string(feed.path("export/feed.json")).unwrap();
+            let json: serde_json::Value = serde_json::from_str(&json).unwrap();
+            assert_eq!(
+                json["items"][0]["tags"],
+                serde_json::json!(["release", "tooling"])
+            );
+        });
+    }
+
+    #[test]
+    fn atom_output_file_stays_inside_export() {
+        let conf = |output_file: &str| Conf {
+            output_file: Some(output_file.to_string()),
+            ..Default::default()
+        };
+        assert_eq!(atom_output_file(&Conf::default()), Ok("feed.xml"));
+        assert_eq!(
+            atom_output_file(&conf("feeds/atom.xml")),
+            Ok("feeds/atom.xml")
+        );
+        for outside in ["/etc/feed.xml", "../feed.xml", "feeds/../../feed.xml", ""] {
+            assert!(atom_output_file(&conf(outside)).is_err(), "{outside}");
+        }
+    }
+
+    #[test]
+    fn git_deploy_builds_on_the_published_branch() {
+        let repos = tempfile::tempdir().unwrap();
+        let remote = repos.path().join("remote.git");
+        let site = repos.path().join("site");
+        // The identity is given to each git the test runs, so the process environment is left alone
+        let run = |dir: &Path, args: &[&str]| {
+            let output = Command::new("git")
+                .arg("-C")
+                .arg(dir)
+                .args(args)
+                .envs([
+                    ("GIT_AUTHOR_NAME", "Adduce"),
+                    ("GIT_AUTHOR_EMAIL", "adduce@example.com"),
+                    ("GIT_COMMITTER_NAME", "Adduce"),
+                    ("GIT_COMMITTER_EMAIL", "adduce@example.com"),
+                ])
+                .output()
+                .unwrap();
+            assert!(output.status.success(), "git {args:?}: {output:?}");
+            String::from_utf8(output.stdout).unwrap()
+        };
+
+        // Publish a first deploy from one clone, then deploy again from a clone that has never checked it out
+        run(repos.path(), &["init", "--quiet", "--bare", "remote.git"]);
+        let first = repos.path().join("first");
+        run(repos.path(), &["clone", "--quiet", "remote.git", "first"]);
+        run(&first, &["checkout", "--quiet", "--orphan", "pages"]);
+        fs::write(first.join("old.html"), "old").unwrap();
+        run(&first, &["add", "--all"]);
+        run(&first, &["commit", "--quiet", "-m", "First deploy"]);
+        run(&first, &["push", "--quiet", "origin", "pages"]);
+        let published = run(&remote, &["rev-parse", "pages"]);
+
+        run(repos.path(), &["clone", "--quiet", "remote.git", "site"]);
+        // The deploy runs git itself, so the clone it commits from carries the identity in its config
+        run(&site, &["config", "user.name", "Adduce"]);
+        run(&site, &["config", "user.email", "adduce@example.com"]);
+        let feed = &Feed::new(site.clone());
+        assert!(!git_ref_exists(feed, &site, "refs/heads/pages"));
+
+        let files = [("export/index.html", "new")];
+        in_feed(&files, |feed| {
+            let config = GitDeploy {
+                repo: Some(site.to_string_lossy().to_string()),
+                branch: "pages".to_string(),
+                remote: None,
+            };
+            deploy_git(feed, &config).unwrap();
+        });
+
+        assert_eq!(run(&remote, &["rev-parse", "pages~1"]), published);
+        assert_eq!(
+            run(&remote, &["ls-tree", "--name-only", "pages"]),
+            "index.html\n"
+        );
+    }
+
+    // Records what a deploy does to a bucket that starts out holding the given keys
+    #[derive(Default)]
+    struct MockBucket {
+        keys: Vec<String>,
+        puts: RefCell<Vec<(String, String, Option<String>)>>,
+        deletes: RefCell<Vec<String>>,
+    }
+
+    impl Bucket for MockBucket {
+        fn put(
+            &self,
+            key: &str,
+            _body: Vec<u8>,
+            content_type: &str,
+            cache_control: Option<&str>,
+        ) -> Result<(), String> {
+            self.puts.borrow_mut().push((
+                key.to_string(),
+                content_type.to_string(),
+                cache_control.map(str::to_string),
+            ));
+            Ok(())
+        }
+
+        fn list(&self, prefix: Option<&str>) -> Result<Vec<String>, String> {
+            Ok(self
+                .keys
+                .iter()
+                .filter(|key| key.starts_with(prefix.unwrap_or_default()))
+                .cloned()
+                .collect())
+        }
+
+        fn delete(&self, key: &str) -> Result<(), String> {
+            self.deletes.borrow_mut().push(key.to_string());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn s3_deploy_uploads_with_content_types_and_deletes_stale_keys() {
+        let files = [
+            ("export/index.html", "<p>Home</p>"),
+            ("export/feed.xml", "<feed/>"),
+            ("export/assets/photo.png", "png"),
+            ("export/.index.html.tmp", "partial"),
+        ];
+        in_feed(&files, |feed| {
+            let bucket = MockBucket {
+                keys: ["blog/index.html", "blog/old.html", "other/keep.html"]
+                    .map(str::to_string)
+                    .to_vec(),
+                ..Default::default()
+            };
+            let config = S3Deploy {
+                bucket: "site".to_string(),
+                prefix: Some("/blog/".to_string()),
+                cache_control: Some("max-age=60".to_string()),
+                ..Default::default()
+            };
+            sync_bucket(feed, &bucket, &config, true).unwrap();
+
+            let cache = Some("max-age=60".to_string());
+            assert_eq!(
+                bucket.puts.into_inner(),
+                [
+                    ("blog/assets/photo.png", "image/png"),
+                    ("blog/feed.xml", "application/xml; charset=utf-8"),
+                    ("blog/index.html", "text/html; charset=utf-8"),
+                ]
+                .map(|(key, content_type)| (
+                    key.to_string(),
+                    content_type.to_string(),
+                    cache.clone()
+                ))
+            );
+            assert_eq!(bucket.deletes.into_inner(), ["blog/old.html"]);
+        });
+    }
+
+    #[test]
+    fn confirm_with_accepts_only_yes() {
+        for (answer, confirmed) in [
+            ("y\n", true),
+            (" YES \n", true),
+            ("n\n", false),
+            ("\n", false),
+            ("yep\n", false),
+            ("", false),
+        ] {
+            assert_eq!(
+                confirm_with("Remove post?", &mut answer.as_bytes()),
+                confirmed,
+                "{answer:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn rsync_args_mirror_the_export_contents() {
+        let rsync = RsyncDeploy {
+            host: "me@example.com".to_string(),
+            path: "/var/www/site".to_string(),
+            flags: Some(vec!["--chmod=F644".to_string()]),
+        };
+        let feed = &Feed::new(PathBuf::from("feed"));
+        assert_eq!(
+            rsync_args(feed, &rsync, false),
+            [
+                "-az",
+                "--delete",
+                "--chmod=F644",
+                "feed/export/",
+                "me@example.com:/var/www/site"
+            ]
+        );
+        assert_eq!(rsync_args(feed, &rsync, true)[2], "-n");
+    }
+
+    #[test]
+    fn split_author_reads_the_rss_email_and_name_form() {
+        assert_eq!(
+            split_author(" me@example.com (Jo Bloggs) "),
+            Some(("me@example.com", "Jo Bloggs"))
+        );
+        assert_eq!(split_author("Jo Bloggs"), None);
+        assert_eq!(split_author("Jo (Bloggs)"), None);
+        assert_eq!(split_author("me@example.com"), None);
+    }
+
+    #[test]
+    fn is_valid_document_name_rejects_paths() {
+        for name in ["post", "my-post.v2", ".hidden"] {
+            assert!(is_valid_document_name(name), "{name}");
+        }
+        for name in [
+            "",
+            ".",
+            "..",
+            "../post",
+            "notes/post",
+            "notes\\post",
+            "po\0st",
+        ] {
+            assert!(!is_valid_document_name(name), "{name:?}");
+        }
+    }
+
+    #[test]
+    fn export_renders_the_body_without_its_front_matter() {
+        let files = [
+            ("conf.toml", CONF),
+            (
+                "documents/post.md",
+                "---\ntitle: Front title\nsecret: kept out\n---\n# Post\n\nThe body.\n",
+            ),
+        ];
+        in_feed(&files, |feed| {
+            cli_export(feed, "post", false).unwrap();
+            let html = fs::read_to_string(feed.path("export/post.html")).unwrap();
+            assert!(html.contains("The body.</p>"));
+            assert!(html.contains("<title>Front title</title>"));
+            assert!(!html.contains("kept out"));
+            assert!(!html.contains("---"));
+            assert!(html.starts_with("<!DOCTYPE html>\n"));
+            assert!(html.ends_with("</body>\n</html>\n"));
+        });
+    }
+
+    #[test]
+    fn export_names_the_document_with_malformed_front_matter() {
+        let files = [
+            ("conf.toml", CONF),
+            (
+                "documents/broken.md",
+                "---\ntitle: [unclosed\n---\n# Broken\n",
+            ),
+        ];
+        in_feed(&files, |feed| {
+            let err = cli_export(feed, "broken", false).unwrap_err();
+            assert!(err.contains("broken"), "{err}");
+            assert!(!feed.path("export/broken.html").exists());
+        });
+    }
+
+    #[test]
+    fn clean_removes_only_generated_files() {
+        let files = [
+            ("export/post.html", ""),
+            ("export/feed.xml", ""),
+            ("export/feed.json", ""),
+            ("export/robots.txt", ""),
+            ("export/style.css", ""),
+            ("export/assets/logo.html", ""),
+        ];
+        in_feed(&files, |feed| {
+            cli_clean(feed, true).unwrap();
+            assert!(feed.path("export/post.html").exists());
+
+            cli_clean(feed, false).unwrap();
+            let mut left = Vec::new();
+            list_files(&feed.path("export"), &mut left).unwrap();
+            left.sort();
+            assert_eq!(
+                left,
+                [
+                    feed.path("export/assets/logo.html"),
+                    feed.path("export/style.css")
+                ]
+            );
+        });
+    }
+
+    #[test]
+    fn remove_deletes_documents_matching_a_pattern() {
+        let files = [
+            ("documents/2023-old.md", ""),
+            ("documents/2024-new.md", ""),
+            ("documents/keep.md", ""),
+            ("export/2023-old.html", ""),
+            ("export/keep.html", ""),
+            ("notes.md", ""),
+        ];
+        in_feed(&files, |feed| {
+            cli_remove(feed, "20*", true).unwrap();
+            assert_eq!(document_names(feed).unwrap(), ["keep"]);
+            assert!(!feed.path("export/2023-old.html").exists());
+            assert!(feed.path("export/keep.html").exists());
+            assert!(feed.path("notes.md").exists());
+
+            assert!(cli_remove(feed, "20*", true).is_err());
+            assert!(cli_remove(feed, "[", true).is_err());
+        });
+    }
+
+    #[test]
+    fn copy_starts_a_new_undated_document() {
+        let files = [(
+            "documents/post.md",
+            "---\ndate: 2024-05-01\ntitle: Post\n---\n# Post\n\nBody.\n",
+        )];
+        in_feed(&files, |feed| {
+            cli_copy(feed, "post", "sequel").unwrap();
+            assert_eq!(
+                fs::read_to_string(feed.path("documents/sequel.md")).unwrap(),
+                "---\ntitle: Post\n---\n# sequel\n\nBody.\n"
+            );
+
+            assert!(cli_copy(feed, "post", "sequel").is_err());
+            assert!(cli_copy(feed, "missing", "other").is_err());
+        });
+    }
+
+    #[test]
+    fn sitemap_lists_exported_documents_except_drafts() {
+        let files = [
+            ("conf.toml", CONF),
+            ("documents/post.md", "# Post\n"),
+            ("documents/draft.md", "---\ndraft: true\n---\n# Draft\n"),
+            ("export/post.html", ""),
+            ("export/draft.html", ""),
+            ("export/feed.xml", ""),
+        ];
+        in_feed(&files, |feed| {
+            cli_sitemap(feed).unwrap();
+            let sitemap = fs::read_to_string(feed.path("export/sitemap.xml")).unwrap();
+            assert_eq!(sitemap.matches("<url>").count(), 1);
+            assert!(sitemap.contains("<loc>https://example.com/post.html</loc>"));
+            assert!(sitemap.contains("<lastmod>"));
+        });
+    }
+
+    #[test]
+    fn sitemap_needs_base_url() {
+        in_feed(&[("conf.toml", "title = \"Site\"\n")], |feed| {
+            assert!(cli_sitemap(feed).unwrap_err().contains("base_url"));
+            assert!(!feed.path("export/sitemap.xml").exists());
+        });
+    }
+
+    #[test]
+    fn feed_root_prefers_the_flag_then_the_environment() {
+        let env_dir = || Some("from-env".to_string());
+        assert_eq!(feed_root(Some("flag"), env_dir()), Path::new("flag"));
+        assert_eq!(feed_root(None, env_dir()), Path::new("from-env"));
+        assert_eq!(feed_root(None, None), Path::new("feed"));
+        assert_eq!(feed_root(None, Some(String::new())), Path::new("feed"));
+        assert_eq!(feed_root(Some(""), env_dir()), Path::new("feed"));
+    }
+
+    #[test]
+    fn establish_and_create_work_in_the_given_directory() {
+        let parent = tempfile::tempdir().unwrap();
+        let dir = parent.path().join("sites/blog");
+        let run = |command: &[&str]| {
+            let mut args = vec!["feed".to_string()];
+            args.extend(command.iter().map(|arg| arg.to_string()));
+            args.extend(["--dir".to_string(), dir.to_string_lossy().to_string()]);
+            process(args)
+        };
+
+        run(&["establish"]).unwrap();
+        run(&["create", "First Post"]).unwrap();
+
+        assert!(dir.join("conf.toml").is_file());
+        assert!(dir.join("export").is_dir());
+        assert_eq!(
+            fs::read_to_string(dir.join("documents/first-post.md")).unwrap(),
+            "# First Post\n"
+        );
+        assert!(!parent.path().join("documents").exists());
+    }
+
+    #[test]
+    fn content_files_are_read_from_the_feed_directory() {
+        let conf = format!(
+            "{CONF}\n[[main.block]]\nformat = \"md\"\ncontent_file = \"./parts/about.md\"\n"
+        );
+        let files = [
+            ("conf.toml", conf.as_str()),
+            ("parts/about.md", "About this site."),
+            ("documents/post.md", "# Post\n"),
+        ];
+        in_feed(&files, |feed| {
+            cli_export(feed, "post", false).unwrap();
+            let html = fs::read_to_string(feed.path("export/post.html")).unwrap();
+            assert!(html.contains("About this site."));
+        });
+    }
+
+    #[test]
+    fn open_with_yes_exports_first_and_launches_the_opener() {
+        let files = [("conf.toml", CONF), ("documents/post.md", "# Post\n")];
+        in_feed(&files, |feed| {
+            let mut launched = Vec::new();
+            open_with(feed, "post", true, |command| {
+                launched.push(command.get_program().to_owned());
+                launched.extend(command.get_args().map(|arg| arg.to_owned()));
+                Ok(ExitStatus::default())
+            })
+            .unwrap();
+
+            let exported = feed.export_path("post");
+            assert!(exported.is_file());
+            let program = if cfg!(target_os = "windows") {
+                "cmd"
+            } else if cfg!(target_os = "macos") {
+                "open"
+            } else {
+                "xdg-open"
+            };
+            assert_eq!(launched.first().unwrap(), program);
+            assert_eq!(launched.last().unwrap(), exported.as_os_str());
+        });
+    }
+
+    #[test]
+    fn open_reports_missing_documents_and_openers() {
+        in_feed(&[("conf.toml", CONF)], |feed| {
+            let err = open_with(feed, "missing", true, |_| unreachable!()).unwrap_err();
+            assert_eq!(err, "No documents with that name.");
+        });
+
+        in_feed(&[("export/post.html", "")], |feed| {
+            let err = open_with(feed, "post", false, |_| {
+                Err(io::Error::from(io::ErrorKind::NotFound))
+            })
+            .unwrap_err();
+            assert!(err.starts_with("No program was found to open"), "{err}");
+        });
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn linked_documents_are_read_and_linked_folders_only_when_following() {
+        use std::os::unix::fs::symlink;
+
+        let files = [
+            ("documents/post.md", "# Post\n"),
+            ("elsewhere/linked.md", "# Linked\n"),
+            ("shared/notes.md", "# Notes\n"),
+        ];
+        in_feed(&files, |feed| {
+            let documents = feed.path("documents");
+            symlink(
+                feed.path("elsewhere/linked.md"),
+                documents.join("linked.md"),
+            )
+            .unwrap();
+            symlink(feed.path("shared"), documents.join("shared")).unwrap();
+            // A link back to documents/ would otherwise be read forever
+            symlink(&documents, feed.path("shared/loop")).unwrap();
+
+            assert_eq!(document_names(feed).unwrap(), ["linked", "post"]);
+
+            let mut following = Feed::new(feed.root.clone());
+            following.follow_symlinks = true;
+            assert_eq!(
+                document_names(&following).unwrap(),
+                ["linked", "notes", "post"]
+            );
+            assert_eq!(
+                following.document_path("notes"),
+                documents.join("shared/notes.md")
+            );
+            assert_eq!(feed.document_path("notes"), documents.join("notes.md"));
+        });
+    }
+
+    // A feed in the same directory that records what it prints instead
+    fn recording(feed: &Feed, verbosity: u8) -> (Feed, Rc<RefCell<Vec<String>>>) {
+        let lines = Rc::new(RefCell::new(Vec::new()));
+        let recorded = Rc::clone(&lines);
+        let mut recording = Feed::new(feed.root.clone());
+        recording.verbosity = verbosity;
+        recording.output = Box::new(move |line| recorded.borrow_mut().push(line.to_string()));
+        (recording, lines)
+    }
+
+    #[test]
+    fn quiet_exports_print_nothing() {
+        let files = [
+            ("conf.toml", CONF),
+            ("documents/first.md", "# First\n"),
+            ("documents/second.md", "# Second\n"),
+        ];
+        in_feed(&files, |feed| {
+            let (quiet, lines) = recording(feed, QUIET);
+            cli_export(&quiet, "first", false).unwrap();
+            cli_export_all(&quiet, false, true).unwrap();
+            cli_atom(&quiet, false).unwrap();
+            cli_remove(&quiet, "sec*", true).unwrap();
+            assert!(feed.export_path("first").is_file());
+            assert_eq!(lines.borrow().len(), 0, "{:?}", lines.borrow());
+        });
+    }
+
+    #[test]
+    fn verbosity_decides_which_lines_are_printed() {
+        let files = [
+            ("conf.toml", CONF),
+            ("documents/post.md", "# Post\n"),
+            ("documents/2023-old.md", ""),
+        ];
+        in_feed(&files, |feed| {
+            let (normal, lines) = recording(feed, NORMAL);
+            cli_export(&normal, "post", false).unwrap();
+            cli_remove(&normal, "2023-*", true).unwrap();
+            assert_eq!(
+                *lines.borrow(),
+                [
+                    "Successfully exported post.",
+                    "Matching documents:",
+                    "    2023-old",
+                    "Deleted source document '2023-old'.",
+                    "No exported document '2023-old' to delete.",
+                ]
+            );
+
+            let (verbose, lines) = recording(feed, VERBOSE);
+            cli_export(&verbose, "post", true).unwrap();
+            let lines = lines.borrow();
+            assert!(lines[0].starts_with("Rendering "), "{lines:?}");
+            assert_eq!(lines[1], "Successfully exported post.");
+        });
+    }
+
+    // The status and body of the response for a URL
+    fn served(feed: &Feed, url: &str) -> (u16, String) {
+        let response = export_response(feed, url);
+        let status = response.status_code().0;
+        let body = String::from_utf8(response.into_reader().into_inner()).unwrap();
+        (status, body)
+    }
+
+    #[test]
+    fn serve_answers_from_the_export_directory() {
+        let files = [
+            ("export/index.html", "<p>Home</p>"),
+            ("export/my post.html", "<p>Post</p>"),
+            ("export/notes/index.html", "<p>Notes</p>"),
+            ("export/404.html", "<p>Missing</p>"),
+        ];
+        in_feed(&files, |feed| {
+            assert_eq!(served(feed, "/"), (200, "<p>Home</p>".to_string()));
+            assert_eq!(served(feed, "/notes/"), (200, "<p>Notes</p>".to_string()));
+            assert_eq!(
+                served(feed, "/my%20post.html?ref=feed#top"),
+                (200, "<p>Post</p>".to_string())
+            );
+            assert_eq!(
+                served(feed, "/missing.html"),
+                (404, "<p>Missing</p>".to_string())
+            );
+        });
+    }
+
+    #[test]
+    fn serve_refuses_paths_outside_the_export_directory() {
+        let files = [("conf.toml", CONF), ("export/index.html", "<p>Home</p>")];
+        in_feed(&files, |feed| {
+            for url in [
+                "/../conf.toml",
+                "/%2e%2e/conf.toml",
+                "/%2E%2E%2Fconf.toml",
+                "/notes/../../conf.toml",
+                "//etc/passwd",
+                "/%2F%2Fetc%2Fpasswd",
+                "/%ff",
+            ] {
+                assert_eq!(
+                    served(feed, url),
+                    (404, "404 Not Found".to_string()),
+                    "{url}"
+                );
+            }
+
+            #[cfg(unix)]
+            {
+                std::os::unix::fs::symlink(feed.path("conf.toml"), feed.path("export/conf.toml"))
+                    .unwrap();
+                assert_eq!(served(feed, "/conf.toml").0, 404);
+            }
+        });
+    }
+
+    #[test]
+    fn front_matter_overrides_the_title_and_inherits_the_language() {
+        let conf = format!("lang = \"en-GB\"\nsubtitle = \"Site description\"\n{CONF}");
+        let files = [
+            ("conf.toml", conf.as_str()),
+            ("documents/post.md", "---\ntitle: Post title\n---\n# Post\n"),
+            ("documents/french.md", "---\nlang: fr\n---\n# Bonjour\n"),
+        ];
+        in_feed(&files, |feed| {
+            cli_export_all(feed, false, false).unwrap();
+
+            let post = fs::read_to_string(feed.export_path("post")).unwrap();
+            assert!(post.contains("<html lang=\"en-GB\">"));
+            assert!(post.contains("<title>Post title</title>"));
+            assert!(post.contains("<meta name=\"description\" content=\"Site description\">"));
+
+            let french = fs::read_to_string(feed.export_path("french")).unwrap();
+            assert!(french.contains("<html lang=\"fr\">"));
+            assert!(french.contains("<title>Site</title>"));
+        });
+    }
+
+    // Read back the Atom feed generated into a feed's export directory
+    fn read_atom(feed: &Feed) -> atom_syndication::Feed {
+        let atom = fs::read_to_string(feed.path("export/feed.xml")).unwrap();
+        atom_syndication::Feed::read_from(atom.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn entries_without_a_date_are_dated_by_their_export() {
+        let files = [
+            ("conf.toml", CONF),
+            ("documents/undated.md", "# Undated\n"),
+            (
+                "documents/unparsable.md",
+                "---\ndate: last tuesday\n---\n# Unparsable\n",
+            ),
+        ];
+        in_feed(&files, |feed| {
+            cli_export_all(feed, false, false).unwrap();
+            let modified = DateTime::parse_from_rfc3339("2021-03-04T05:06:07Z").unwrap();
+            for document in ["undated", "unparsable"] {
+                fs::File::options()
+                    .write(true)
+                    .open(feed.export_path(document))
+                    .unwrap()
+                    .set_modified(modified.into())
+                    .unwrap();
+            }
+
+            cli_atom(feed, false).unwrap();
+            let atom = read_atom(feed);
+            assert_eq!(atom.entries().len(), 2);
+            for entry in atom.entries() {
+                assert_eq!(*entry.updated(), modified, "{}", entry.id());
+                assert_eq!(entry.published(), None, "{}", entry.id());
+            }
+        });
+    }
+
+    #[test]
+    fn entry_ids_stay_the_same_across_rebuilds() {
+        let files = [("conf.toml", CONF), ("documents/post.md", "# Post\n")];
+        in_feed(&files, |feed| {
+            let ids = || {
+                cli_export(feed, "post", true).unwrap();
+                cli_atom(feed, false).unwrap();
+                cli_json(feed, false).unwrap();
+                let json = fs::read_to_string(feed.path("export/feed.json")).unwrap();
+                let json: serde_json::Value = serde_json::from_str(&json).unwrap();
+                (
+                    read_atom(feed).entries()[0].id().to_string(),
+                    json["items"][0]["id"].as_str().unwrap().to_string(),
+                )
+            };
+
+            let first = ids();
+            assert_eq!(first.0, "https://example.com/post");
+            assert_eq!(first.1, first.0);
+            assert_eq!(ids(), first);
+        });
+    }
+
+    #[test]
+    fn feeds_need_an_id_to_derive_entry_ids_from() {
+        let files = [("conf.toml", "title = \"Site\"\n")];
+        in_feed(&files, |feed| {
+            let err = cli_atom(feed, false).unwrap_err();
+            assert!(err.ends_with("Missing required fields: id."), "{err}");
+            assert!(!feed.path("export/feed.xml").exists());
+        });
+    }
+
+    #[test]
+    fn entries_with_the_same_date_are_ordered_by_name() {
+        let files = [
+            ("conf.toml", CONF),
+            ("documents/beta.md", "---\ndate: 2022-02-02\n---\n# Beta\n"),
+            (
+                "documents/alpha.md",
+                "---\ndate: 2022-02-02\n---\n# Alpha\n",
+            ),
+            (
+                "documents/newest.md",
+                "---\ndate: 2024-01-01T09:30:00+02:00\n---\n# Newest\n",
+            ),
+        ];
+        in_feed(&files, |feed| {
+            cli_export_all(feed, false, false).unwrap();
+            cli_atom(feed, false).unwrap();
+            let ids = read_atom(feed)
+                .entries()
+                .iter()
+                .map(|entry| entry.id().to_string())
+                .collect::<Vec<_>>();
+            assert_eq!(
+                ids,
+                ["newest", "alpha", "beta"].map(|slug| format!("https://example.com/{slug}"))
+            );
+        });
+    }
+
+    #[test]
+    fn max_items_keeps_only_the_newest_entries() {
+        let documents = (1..=5)
+            .map(|day| {
+                (
+                    format!("documents/day-{day}.md"),
+                    format!("---\ndate: 2024-01-0{day}\n---\n# Day {day}\n"),
+                )
+            })
+            .collect::<Vec<_>>();
+        for (max_items, expected) in [
+            ("max_items = 2\n", &["day-5", "day-4"][..]),
+            ("", &["day-5", "day-4", "day-3", "day-2", "day-1"][..]),
+        ] {
+            let conf = format!("{max_items}{CONF}");
+            let mut files = vec![("conf.toml", conf.as_str())];
+            files.extend(
+                documents
+                    .iter()
+                    .map(|(path, content)| (path.as_str(), content.as_str())),
+            );
+            in_feed(&files, |feed| {
+                cli_export_all(feed, false, false).unwrap();
+                let pages = collect_pages(feed, &export_conf(feed).unwrap(), false).unwrap();
+                let slugs = pages
+                    .iter()
+                    .map(|page| page.slug.as_str())
+                    .collect::<Vec<_>>();
+                assert_eq!(slugs, expected, "{max_items}");
+            });
+        }
+    }
+
+    #[test]
+    fn enclosures_fill_in_length_and_type_from_assets() {
+        let files = [("assets/episode-2.mp3", "twelve bytes")];
+        in_feed(&files, |feed| {
+            let enclosure = |url: &str, length, mime_type: Option<&str>| Enclosure {
+                url: url.to_string(),
+                length,
+                mime_type: mime_type.map(str::to_string),
+            };
+
+            let explicit = enclosure_link(
+                feed,
+                enclosure(
+                    "https://cdn.example.com/episode-1.mp3",
+                    Some(1234),
+                    Some("audio/mpeg"),
+                ),
+            );
+            assert_eq!(explicit.href(), "https://cdn.example.com/episode-1.mp3");
+            assert_eq!(explicit.rel(), "enclosure");
+            assert_eq!(explicit.length(), Some("1234"));
+            assert_eq!(explicit.mime_type(), Some("audio/mpeg"));
+
+            let inferred = enclosure_link(
+                feed,
+                enclosure("https://cdn.example.com/episode-2.mp3", None, None),
+            );
+            assert_eq!(inferred.length(), Some("12"));
+            assert_eq!(inferred.mime_type(), Some("audio/mpeg"));
+
+            // Without a local copy there is nothing to measure, so the length is left out
+            let missing = enclosure_link(
+                feed,
+                enclosure("https://cdn.example.com/episode-3.mp3", None, None),
+            );
+            assert_eq!(missing.length(), None);
+        });
+    }
+
+    #[test]
+    fn front_matter_enclosures_reach_the_atom_entry() {
+        let files = [
+            ("conf.toml", CONF),
+            (
+                "documents/episode.md",
+                "---\nenclosure:\n  url: https://cdn.example.com/episode.mp3\n  length: 99\n  type: audio/mpeg\n---\n# Episode\n",
+            ),
+        ];
+        in_feed(&files, |feed| {
+            cli_export(feed, "episode", false).unwrap();
+            cli_atom(feed, false).unwrap();
+            let atom = read_atom(feed);
+            let enclosure = atom.entries()[0]
+                .links()
+                .iter()
+                .find(|link| link.rel() == "enclosure")
+                .unwrap();
+            assert_eq!(enclosure.href(), "https://cdn.example.com/episode.mp3");
+            assert_eq!(enclosure.length(), Some("99"));
+        });
+    }
+
+    #[test]
+    fn atom_feed_has_one_entry_per_exported_document() {
+        let conf = format!("author = \"me@example.com (Jo Bloggs)\"\n{CONF}");
+        let files = [
+            ("conf.toml", conf.as_str()),
+            ("documents/one.md", "# One\n"),
+            ("documents/two.md", "# Two\n"),
+            ("documents/three.md", "# Three\n"),
+            ("export/style.css", "body {}"),
+            ("export/feed.json", "{}"),
+        ];
+        in_feed(&files, |feed| {
+            cli_export_all(feed, false, false).unwrap();
+            cli_atom(feed, false).unwrap();
+
+            let xml = fs::read_to_string(feed.path("export/feed.xml")).unwrap();
+            assert!(xml.contains("xmlns=\"http://www.w3.org/2005/Atom\""));
+
+            let atom = read_atom(feed);
+            assert_eq!(atom.id(), "https://example.com/");
+            assert_eq!(atom.title().as_str(), "Site");
+            let mut titles = atom
+                .entries()
+                .iter()
+                .map(|entry| entry.title().as_str())
+                .collect::<Vec<_>>();
+            titles.sort();
+            assert_eq!(titles, ["One", "Three", "Two"]);
+            for entry in atom.entries() {
+                assert_eq!(entry.authors()[0].name(), "Jo Bloggs");
+                assert!(entry.content().is_some());
+            }
+        });
+    }
+
+    #[test]
+    fn json_feed_maps_the_site_and_each_document() {
+        let conf =
+            format!("subtitle = \"About the site\"\nbase = \"https://example.com/\"\n{CONF}");
+        let files = [
+            ("conf.toml", conf.as_str()),
+            (
+                "documents/one.md",
+                "---\ndate: 2024-01-01\n---\n# One\n\nFirst.\n",
+            ),
+            (
+                "documents/two.md",
+                "---\ndate: 2024-02-01\n---\n# Two\n\nSecond.\n",
+            ),
+        ];
+        in_feed(&files, |feed| {
+            cli_export_all(feed, false, false).unwrap();
+            cli_json(feed, false).unwrap();
+
+            let json = fs::read_to_string(feed.path("export/feed.json")).unwrap();
+            let json: serde_json::Value = serde_json::from_str(&json).unwrap();
+            assert_eq!(json["version"], "https://jsonfeed.org/version/1.1");
+            assert_eq!(json["title"], "Site");
+            assert_eq!(json["description"], "About the site");
+            assert_eq!(json["home_page_url"], "https://example.com/");
+            assert_eq!(json["feed_url"], "https://example.com/feed.json");
+
+            let items = json["items"].as_array().unwrap();
+            assert_eq!(items.len(), 2);
+            assert_eq!(items[0]["title"], "Two");
+            assert_eq!(items[0]["url"], "https://example.com/two.html");
+            assert_eq!(items[0]["date_published"], "2024-02-01T00:00:00+00:00");
+            assert!(items[0]["content_html"]
+                .as_str()
+                .unwrap()
+                .contains("Second."));
+        });
+    }
+
+    #[test]
+    fn json_feed_needs_a_title_and_id() {
+        let files = [("conf.toml", "subtitle = \"No title or id\"\n")];
+        in_feed(&files, |feed| {
+            let err = cli_json(feed, false).unwrap_err();
+            assert_eq!(
+                err,
+                "JSON feed not generated. Missing required fields: title, id."
+            );
+            assert!(!feed.path("export/feed.json").exists());
+        });
+    }
+
+    #[test]
+    fn entries_credit_their_own_author_or_the_sites() {
+        let conf = format!("author = \"site@example.com (Site Owner)\"\n{CONF}");
+        let files = [
+            ("conf.toml", conf.as_str()),
+            (
+                "documents/guest.md",
+                "---\nauthor: guest@example.com (Guest Writer)\n---\n# Guest\n",
+            ),
+            (
+                "documents/plain.md",
+                "---\nauthor: Just A Name\n---\n# Plain\n",
+            ),
+            ("documents/own.md", "# Own\n"),
+        ];
+        in_feed(&files, |feed| {
+            cli_export_all(feed, false, false).unwrap();
+            cli_atom(feed, false).unwrap();
+            let atom = read_atom(feed);
+            let author = |id: &str| {
+                let entry = atom
+                    .entries()
+                    .iter()
+                    .find(|entry| entry.id().ends_with(id))
+                    .unwrap();
+                let person = &entry.authors()[0];
+                (
+                    person.name().to_string(),
+                    person.email().map(str::to_string),
+                )
+            };
+
+            assert_eq!(
+                author("/guest"),
+                (
+                    "Guest Writer".to_string(),
+                    Some("guest@example.com".to_string())
+                )
+            );
+            // An author not in the `email (Name)` form is kept as just a name
+            assert_eq!(author("/plain"), ("Just A Name".to_string(), None));
+            assert_eq!(
+                author("/own"),
+                (
+                    "Site Owner".to_string(),
+                    Some("site@example.com".to_string())
+                )
+            );
+        });
+    }
+
+    #[test]
+    fn atom_is_written_to_a_nested_output_file() {
+        let conf = format!(
+            "output_file = \"feeds/atom/index.xml\"\nbase = \"https://example.com\"\n{CONF}"
+        );
+        let files = [("conf.toml", conf.as_str())];
+        in_feed(&files, |feed| {
+            cli_atom(feed, false).unwrap();
+            let xml = fs::read_to_string(feed.path("export/feeds/atom/index.xml")).unwrap();
+            assert!(xml.contains("href=\"https://example.com/feeds/atom/index.xml\""));
+            assert!(!feed.path("export/feed.xml").exists());
+        });
+    }
+
+    #[test]
+    fn atom_refuses_output_files_outside_export() {
+        for output_file in ["../feed.xml", "/tmp/feed.xml", ""] {
+            let conf = format!("output_file = \"{output_file}\"\n{CONF}");
+            in_feed(&[("conf.toml", conf.as_str())], |feed| {
+                let err = cli_atom(feed, false).unwrap_err();
+                assert!(err.contains("must be a relative path"), "{err}");
+                assert!(!feed.path("feed.xml").exists());
+            });
+        }
+    }
+
+    #[test]
+    fn list_shows_sorted_documents_and_their_exports() {
+        let files = [
+            ("documents/zebra.md", "# Zebra\n"),
+            ("documents/apple.md", "# Apple\n"),
+            ("documents/mango.md", "# Mango\n"),
+            ("export/mango.html", "<p>Mango</p>"),
+        ];
+        in_feed(&files, |feed| {
+            assert_eq!(
+                document_listing(feed, false).unwrap(),
+                ["apple", "mango (exported)", "zebra"]
+            );
+            assert_eq!(document_listing(feed, true).unwrap(), ["mango (exported)"]);
+        });
+    }
+
+    #[test]
+    fn list_reports_an_empty_or_missing_documents_folder() {
+        in_feed(&[], |feed| {
+            assert_eq!(
+                document_listing(feed, false).unwrap(),
+                ["No documents yet."]
+            );
+            fs::remove_dir(feed.path("documents")).unwrap();
+            assert!(document_listing(feed, false)
+                .unwrap_err()
+                .contains("adduce feed establish"));
+        });
+    }
+
+    #[test]
+    fn rename_moves_the_document_its_export_and_heading() {
+        let files = [
+            ("documents/old.md", "# old\n\nThe body.\n"),
+            ("export/old.html", "<p>The body.</p>"),
+        ];
+        in_feed(&files, |feed| {
+            cli_rename(feed, "old", "new").unwrap();
+            assert!(!feed.path("documents/old.md").exists());
+            assert!(!feed.path("export/old.html").exists());
+            assert_eq!(
+                fs::read_to_string(feed.path("documents/new.md")).unwrap(),
+                "# new\n\nThe body.\n"
+            );
+            assert_eq!(
+                fs::read_to_string(feed.path("export/new.html")).unwrap(),
+                "<p>The body.</p>"
+            );
+        });
+    }
+
+    #[test]
+    fn rename_keeps_headings_that_were_edited() {
+        let files = [("documents/old.md", "# A better title\n")];
+        in_feed(&files, |feed| {
+            cli_rename(feed, "old", "new").unwrap();
+            assert_eq!(
+                fs::read_to_string(feed.path("documents/new.md")).unwrap(),
+                "# A better title\n"
+            );
+        });
+    }
+
+    #[test]
+    fn rename_refuses_to_overwrite_a_destination() {
+        let files = [
+            ("documents/old.md", "# old\n"),
+            ("documents/new.md", "# new\n"),
+        ];
+        in_feed(&files, |feed| {
+            let err = cli_rename(feed, "old", "new").unwrap_err();
+            assert!(err.contains("already exists"), "{err}");
+            assert_eq!(
+                fs::read_to_string(feed.path("documents/old.md")).unwrap(),
+                "# old\n"
+            );
+            assert_eq!(
+                fs::read_to_string(feed.path("documents/new.md")).unwrap(),
+                "# new\n"
+            );
+        });
+        let files = [("documents/old.md", "# old\n"), ("export/new.html", "")];
+        in_feed(&files, |feed| {
+            assert!(cli_rename(feed, "old", "new").is_err());
+            assert!(feed.path("documents/old.md").exists());
+        });
+    }
+
+    #[test]
+    fn rename_needs_an_existing_document() {
+        in_feed(&[], |feed| {
+            assert_eq!(
+                cli_rename(feed, "missing", "new"),
+                Err("No documents with that name.".to_string())
+            );
+        });
+    }
+
+    #[test]
+    fn search_finds_words_in_document_bodies() {
+        let files = [
+            ("documents/rust.md", "# Rust\n\nAbout Ferris.\n"),
+            ("documents/cooking.md", "# Cooking\n\nNothing here.\n"),
+            ("documents/ferris.md", "# Ferris\n\nFerris again.\n"),
+        ];
+        in_feed(&files, |feed| {
+            assert_eq!(
+                search_documents(feed, "ferris", false, false).unwrap(),
+                ["ferris", "rust:3: About Ferris."]
+            );
+            assert_eq!(
+                search_documents(feed, "ferris", true, false).unwrap(),
+                ["ferris"]
+            );
+            assert!(search_documents(feed, "missing", false, false)
+                .unwrap()
+                .is_empty());
+        });
+    }
+
+    #[test]
+    fn search_rejects_invalid_patterns_and_missing_folders() {
+        in_feed(&[("documents/post.md", "# Post\n")], |feed| {
+            assert_eq!(
+                search_documents(feed, "(", false, false).unwrap(),
+                Vec::<String>::new()
+            );
+            assert!(search_documents(feed, "(", false, true)
+                .unwrap_err()
+                .contains("Invalid regular expression"));
+            fs::remove_dir_all(feed.path("documents")).unwrap();
+            assert!(search_documents(feed, "post", false, false)
+                .unwrap_err()
+                .contains("adduce feed establish"));
+        });
+    }
+
+    #[test]
+    fn search_ignores_case_unless_asked_not_to() {
+        let files = [
+            ("documents/Rust-Notes.md", "# Notes\n"),
+            ("documents/rusty.md", "# Rusty\n"),
+            ("documents/python.md", "# Python\n"),
+        ];
+        in_feed(&files, |feed| {
+            assert_eq!(
+                search_documents(feed, "RUST", false, false).unwrap(),
+                ["Rust-Notes", "rusty"]
+            );
+            assert_eq!(
+                search_documents(feed, "Rust", true, false).unwrap(),
+                ["Rust-Notes", "rusty:1: # Rusty"]
+            );
+            assert!(search_documents(feed, "RUST", true, false)
+                .unwrap()
+                .is_empty());
+        });
+    }
+
+    #[test]
+    fn search_matches_regular_expressions() {
+        let files = [
+            ("documents/2023-launch.md", "# Launch\n"),
+            ("documents/2024-review.md", "# Review\n"),
+            ("documents/about-2024.md", "# About\n"),
+        ];
+        in_feed(&files, |feed| {
+            assert_eq!(
+                search_documents(feed, r"^20\d\d-", false, true).unwrap(),
+                ["2023-launch", "2024-review"]
+            );
+            assert!(search_documents(feed, r"^20\d\d-", false, false)
+                .unwrap()
+                .is_empty());
+            assert!(search_documents(feed, r"^19\d\d-", false, true)
+                .unwrap()
+                .is_empty());
+            let err = search_documents(feed, "20[", false, true).unwrap_err();
+            assert!(err.starts_with("Invalid regular expression '20['"), "{err}");
+        });
+    }
+
+    #[test]
+    fn export_all_exports_every_document() {
+        let files = [
+            ("conf.toml", CONF),
+            ("documents/first.md", "# First\n"),
+            ("documents/second.md", "# Second\n"),
+            ("documents/third.md", "# Third\n"),
+        ];
+        in_feed(&files, |feed| {
+            let (recording, lines) = recording(feed, NORMAL);
+            cli_export_all(&recording, false, false).unwrap();
+            for (document, title) in [("first", "First"), ("second", "Second"), ("third", "Third")]
+            {
+                let html = fs::read_to_string(feed.export_path(document)).unwrap();
+                assert!(html.contains(title), "{document}");
+            }
+            assert!(lines.borrow().contains(
+                &"Exported 3 documents, 0 failed, 0 drafts skipped, 0 existing skipped."
+                    .to_string()
+            ));
+        });
+    }
+
+    #[test]
+    fn export_all_carries_on_past_a_failing_document() {
+        let files = [
+            ("conf.toml", CONF),
+            ("documents/first.md", "# First\n"),
+            ("documents/broken.md", "+++\ntitle =\n+++\n# Broken\n"),
+            ("documents/third.md", "# Third\n"),
+        ];
+        in_feed(&files, |feed| {
+            let (recording, lines) = recording(feed, NORMAL);
+            let err = cli_export_all(&recording, false, false).unwrap_err();
+            assert_eq!(err, "1 documents failed to export.");
+            assert!(feed.export_path("first").exists());
+            assert!(feed.export_path("third").exists());
+            assert!(!feed.export_path("broken").exists());
+            assert!(lines.borrow().contains(
+                &"Exported 2 documents, 1 failed, 0 drafts skipped, 0 existing skipped."
+                    .to_string()
+            ));
+        });
+    }
+
+    #[test]
+    fn rebuild_exports_only_the_changed_document() {
+        let files = [
+            ("conf.toml", CONF),
+            ("documents/edited.md", "# Edited\n"),
+            ("documents/untouched.md", "# Untouched\n"),
+        ];
+        in_feed(&files, |feed| {
+            let (recording, lines) = recording(feed, NORMAL);
+            rebuild(
+                &recording,
+                &BTreeSet::from([feed.path("documents/edited.md")]),
+                false,
+            );
+            assert!(feed.export_path("edited").exists());
+            assert!(!feed.export_path("untouched").exists());
+            let exported = lines
+                .borrow()
+                .iter()
+                .filter(|line| line.starts_with("Exported"))
+                .count();
+            assert_eq!(exported, 1);
+        });
+    }
+
+    #[test]
+    fn rebuild_exports_everything_when_the_configuration_changes() {
+        let files = [
+            ("conf.toml", CONF),
+            ("documents/first.md", "# First\n"),
+            ("documents/second.md", "# Second\n"),
+        ];
+        in_feed(&files, |feed| {
+            rebuild(feed, &BTreeSet::from([feed.path("conf.toml")]), false);
+            assert!(feed.export_path("first").exists());
+            assert!(feed.export_path("second").exists());
+        });
+    }
+
+    #[test]
+    fn rebuild_ignores_unrelated_and_deleted_files() {
+        let files = [("conf.toml", CONF), ("documents/notes.txt", "")];
+        in_feed(&files, |feed| {
+            let (recording, lines) = recording(feed, NORMAL);
+            let changed = BTreeSet::from([
+                feed.path("documents/notes.txt"),
+                feed.path("documents/deleted.md"),
+                feed.path("export/index.html"),
+            ]);
+            rebuild(&recording, &changed, false);
+            assert!(lines.borrow().is_empty());
+            assert!(!feed.export_path("deleted").exists());
+        });
+    }
+
+    #[test]
+    fn drafts_are_left_out_unless_asked_for() {
+        let files = [
+            ("conf.toml", CONF),
+            ("documents/published.md", "# Published\n"),
+            (
+                "documents/wip.md",
+                "---\ndraft: true\n---\n# Work in progress\n",
+            ),
+        ];
+        in_feed(&files, |feed| {
+            cli_export_all(feed, false, false).unwrap();
+            assert!(feed.export_path("published").exists());
+            assert!(!feed.export_path("wip").exists());
+
+            // Naming a draft still exports it for previewing, but it stays out of the feed
+            cli_export(feed, "wip", false).unwrap();
+            assert!(feed.export_path("wip").exists());
+            cli_atom(feed, false).unwrap();
+            let titles = |atom: atom_syndication::Feed| {
+                atom.entries()
+                    .iter()
+                    .map(|entry| entry.title().to_string())
+                    .collect::<Vec<_>>()
+            };
+            assert_eq!(titles(read_atom(feed)), ["Published"]);
+
+            cli_atom(feed, true).unwrap();
+            assert_eq!(titles(read_atom(feed)).len(), 2);
+        });
+    }
+
+    #[test]
+    fn create_fills_in_a_template() {
+        let files = [("templates/post.md", "# {{title}}\n\nWritten {{date}}.\n")];
+        in_feed(&files, |feed| {
+            cli_create(feed, "Hello", Some("post"), false, false).unwrap();
+            let date = Local::now().format("%Y-%m-%d");
+            assert_eq!(
+                fs::read_to_string(feed.path("documents/hello.md")).unwrap(),
+                format!("# Hello\n\nWritten {date}.\n")
+            );
+
+            cli_create(feed, "Plain", None, false, false).unwrap();
+            assert_eq!(
+                fs::read_to_string(feed.path("documents/plain.md")).unwrap(),
+                "# Plain\n"
+            );
+        });
+    }
+
+    #[test]
+    fn create_with_a_missing_template_creates_nothing() {
+        in_feed(&[], |feed| {
+            let err = cli_create(feed, "Hello", Some("missing"), false, false).unwrap_err();
+            assert!(err.starts_with("Failed to read template"), "{err}");
+            assert!(!feed.path("documents/hello.md").exists());
+        });
+    }
+
+    #[test]
+    fn search_shows_document_names_without_extensions() {
+        let files = [
+            ("documents/post.md", "# Post\n"),
+            ("documents/notes.txt", "post"),
+            ("documents/folder.md/inner.md", "# Inner\n"),
+        ];
+        in_feed(&files, |feed| {
+            assert_eq!(
+                search_documents(feed, "post", false, false).unwrap(),
+                ["post"]
+            );
+            assert!(search_documents(feed, ".md", false, false)
+                .unwrap()
+                .is_empty());
+            assert!(search_documents(feed, "folder", false, false)
+                .unwrap()
+                .is_empty());
+        });
+    }
+
+    #[test]
+    fn stats_count_the_text_but_not_the_markup() {
+        let post = "---\ntitle: A long title that is not counted\n---\n# Hello world\n\nSome **bold** words and a [link](https://example.com).\n";
+        in_feed(&[("documents/post.md", post)], |feed| {
+            let stats = document_stats(feed, "post").unwrap();
+            assert_eq!(stats.words, 8);
+            assert_eq!(
+                stats.characters,
+                "Helloworld".len() + "Someboldwordsandalink.".len()
+            );
+            assert_eq!(stats.reading_minutes(), 1);
+            assert!(!stats.draft);
+        });
+    }
+
+    #[test]
+    fn stats_estimate_reading_time_and_flag_drafts() {
+        let post = format!("---\ndraft: true\n---\n{}", "word ".repeat(401));
+        in_feed(&[("documents/post.md", post.as_str())], |feed| {
+            let stats = document_stats(feed, "post").unwrap();
+            assert_eq!(stats.words, 401);
+            assert_eq!(stats.reading_minutes(), 3);
+            assert!(stats.draft);
+        });
+        in_feed(&[], |feed| {
+            let err = document_stats(feed, "missing").err().unwrap();
+            assert!(err.starts_with("Failed to read"), "{err}");
+        });
+    }
+
+    #[test]
+    fn validate_accepts_a_complete_configuration() {
+        in_feed(&[("conf.toml", CONF)], |feed| {
+            assert_eq!(cli_validate(feed), Ok(()));
+        });
+    }
+
+    #[test]
+    fn validate_names_the_missing_feed_fields() {
+        let files = [("conf.toml", "title = \"Site\"\n")];
+        in_feed(&files, |feed| {
+            assert_eq!(
+                cli_validate(feed),
+                Err("Missing fields required for feeds: id.".to_string())
+            );
+        });
+        in_feed(&[("conf.toml", "")], |feed| {
+            assert_eq!(
+                cli_validate(feed),
+                Err("Missing fields required for feeds: title, id.".to_string())
+            );
+        });
+    }
+
+    #[test]
+    fn validate_reports_unreadable_configurations() {
+        in_feed(&[], |feed| {
+            let err = cli_validate(feed).unwrap_err();
+            assert!(err.starts_with("Error reading configuration file"), "{err}");
+        });
+        in_feed(&[("conf.toml", "title = \"Site\"\nid = \n")], |feed| {
+            let err = cli_validate(feed).unwrap_err();
+            assert!(err.starts_with("Error parsing configuration file"), "{err}");
+            assert!(err.contains("line 2"), "{err}");
+        });
+    }
+
+    #[test]
+    fn establish_writes_a_configuration_that_feeds_accept() {
+        let dir = tempfile::tempdir().unwrap();
+        let feed = Feed::new(dir.path().join("feed"));
+        cli_establish(&feed).unwrap();
+        assert!(feed.path("documents").is_dir());
+        assert!(feed.path("export").is_dir());
+        assert_eq!(
+            fs::read_to_string(feed.path("conf.toml")).unwrap(),
+            STARTER_CONF
+        );
+        assert_eq!(cli_validate(&feed), Ok(()));
+
+        fs::write(feed.path("documents/first.md"), "# First\n").unwrap();
+        cli_export(&feed, "first", false).unwrap();
+        cli_atom(&feed, false).unwrap();
+        assert_eq!(read_atom(&feed).entries().len(), 1);
+    }
+
+    #[test]
+    fn establish_leaves_an_existing_configuration_alone() {
+        in_feed(&[("conf.toml", "title = \"Mine\"\n")], |feed| {
+            cli_establish(feed).unwrap();
+            assert_eq!(
+                fs::read_to_string(feed.path("conf.toml")).unwrap(),
+                "title = \"Mine\"\n"
+            );
+        });
+    }
+
+    #[test]
+    fn configuration_syntax_errors_point_at_the_line() {
+        let files = [
+            ("conf.toml", "title = \"Site\"\nid = \"unterminated\n"),
+            ("documents/post.md", "# Post\n"),
+        ];
+        in_feed(&files, |feed| {
+            for err in [
+                cli_export(feed, "post", false).unwrap_err(),
+                cli_atom(feed, false).unwrap_err(),
+                cli_json(feed, false).unwrap_err(),
+            ] {
+                assert!(err.starts_with("Error parsing configuration file"), "{err}");
+                assert!(err.contains("line 2, column"), "{err}");
+                assert!(err.contains("2 | id = \"unterminated"), "{err}");
+                assert!(err.contains('^'), "{err}");
+            }
+            assert!(!feed.export_path("post").exists());
+        });
+    }
+
+    #[test]
+    fn the_site_author_is_credited_on_every_feed() {
+        let conf = format!("author = \"me@example.com (Jo Bloggs)\"\n{CONF}");
+        let files = [
+            ("conf.toml", conf.as_str()),
+            ("documents/one.md", "# One\n"),
+            ("documents/two.md", "# Two\n"),
+        ];
+        in_feed(&files, |feed| {
+            cli_export_all(feed, false, false).unwrap();
+            cli_atom(feed, false).unwrap();
+            cli_json(feed, false).unwrap();
+
+            let atom = read_atom(feed);
+            assert_eq!(atom.authors()[0].name(), "Jo Bloggs");
+            assert_eq!(atom.authors()[0].email(), Some("me@example.com"));
+            assert!(atom
+                .entries()
+                .iter()
+                .all(|entry| entry.authors() == atom.authors()));
+
+            let json = fs::read_to_string(feed.path("export/feed.json")).unwrap();
+            let json: serde_json::Value = serde_json::from_str(&json).unwrap();
+            let author =
+                serde_json::json!([{ "name": "Jo Bloggs", "url": "mailto:me@example.com" }]);
+            assert_eq!(json["authors"], author);
+            let items = json["items"].as_array().unwrap();
+            assert_eq!(items.len(), 2);
+            assert!(items.iter().all(|item| item["authors"] == author));
+        });
+    }
+
+    #[test]
+    fn feeds_without_any_author_leave_it_out() {
+        let files = [("conf.toml", CONF), ("documents/one.md", "# One\n")];
+        in_feed(&files, |feed| {
+            cli_export_all(feed, false, false).unwrap();
+            cli_atom(feed, false).unwrap();
+            cli_json(feed, false).unwrap();
+
+            let atom = read_atom(feed);
+            assert!(atom.authors().is_empty());
+            assert!(atom.entries()[0].authors().is_empty());
+            let json = fs::read_to_string(feed.path("export/feed.json")).unwrap();
+            assert!(!json.contains("authors"));
+        });
+    }
+
+    #[test]
+    fn exported_wikilinks_resolve_against_the_feed() {
+        let files = [
+            ("conf.toml", CONF),
+            (
+                "documents/post.md",
+                "# Post\n\nSee [[Other]] and [[Gone]].\n",
+            ),
+            ("documents/other.md", "# Other\n"),
+        ];
+        in_feed(&files, |feed| {
+            cli_export(feed, "post", false).unwrap();
+            let html = fs::read_to_string(feed.export_path("post")).unwrap();
+            assert!(html.contains("<a href=\"other.html\">Other</a>"), "{html}");
+            assert!(html.contains("class=\"broken-link\""), "{html}");
+        });
+    }
+
+    #[test]
+    fn establish_creates_missing_parents_and_can_run_again() {
+        let dir = tempfile::tempdir().unwrap();
+        let feed = Feed::new(dir.path().join("sites/blog/feed"));
+        cli_establish(&feed).unwrap();
+        cli_establish(&feed).unwrap();
+        assert!(feed.path("documents").is_dir());
+        assert!(feed.path("export").is_dir());
+    }
+
+    #[test]
+    fn establish_names_a_folder_it_cannot_create() {
+        let dir = tempfile::tempdir().unwrap();
+        let feed = Feed::new(dir.path().to_path_buf());
+        fs::write(feed.path("export"), "").unwrap();
+        let err = cli_establish(&feed).unwrap_err();
+        assert!(
+            err.starts_with(&format!(
+                "Failed to create {}",
+                feed.path("export").display()
+            )),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn feeds_skip_unreadable_and_odd_exports() {
+        let files = [
+            ("conf.toml", CONF),
+            ("documents/good.md", "# Good\n"),
+            ("export/.hidden.html", "<p>Hidden</p>"),
+            ("export/folder.html/index.html", "<p>Nested</p>"),
+        ];
+        in_feed(&files, |feed| {
+            cli_export(feed, "good", false).unwrap();
+            fs::write(feed.path("export/bad.html"), [0xff, 0xfe, 0xfd]).unwrap();
+            cli_atom(feed, false).unwrap();
+            let atom = read_atom(feed);
+            assert_eq!(atom.entries().len(), 1);
+            assert_eq!(atom.entries()[0].title().as_str(), "Good");
+        });
+    }
+
+    #[test]
+    fn feeds_fail_when_no_export_can_be_read() {
+        in_feed(&[("conf.toml", CONF)], |feed| {
+            fs::write(feed.path("export/bad.html"), [0xff, 0xfe, 0xfd]).unwrap();
+            assert_eq!(
+                cli_atom(feed, false),
+                Err(
+                    "Atom feed not generated. None of the exported pages could be read."
+                        .to_string()
+                )
+            );
+            assert!(!feed.path("export/feed.xml").exists());
+
+            fs::remove_dir_all(feed.path("export")).unwrap();
+            let err = cli_atom(feed, false).unwrap_err();
+            assert!(err.contains("Failed to read export directory"), "{err}");
+        });
+    }
+
+    #[test]
+    fn search_before_establish_explains_what_to_do() {
+        let dir = tempfile::tempdir().unwrap();
+        let feed = Feed::new(dir.path().join("feed"));
+        let err = cli_search(&feed, "post", false, false).unwrap_err();
+        assert!(
+            err.starts_with("Failed to read the documents folder"),
+            "{err}"
+        );
+        assert!(
+            err.ends_with("Run `adduce feed establish` to create it."),
+            "{err}"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn search_skips_names_that_are_not_unicode() {
+        use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+        in_feed(&[("documents/post.md", "# Post\n")], |feed| {
+            let odd = feed
+                .path("documents")
+                .join(OsStr::from_bytes(b"post-\xff.md"));
+            fs::write(odd, "# Post\n").unwrap();
+            assert_eq!(
+                search_documents(feed, "post", false, false).unwrap(),
+                ["post"]
+            );
+        });
+    }
+
+    #[test]
+    fn documents_already_in_the_configuration_are_exported_once() {
+        let conf = r#"
+title = "Site"
+
+[[main.block]]
+format = "header"
+content = "Top"
+
+[[main.block]]
+format = "md"
+content_file = "documents/post.md"
+
+[[main.block]]
+format = "footer"
+content = "Bottom"
+"#;
+        let files = [
+            ("conf.toml", conf),
+            ("documents/post.md", "# Post\n\nA unique sentence.\n"),
+        ];
+        in_feed(&files, |feed| {
+            cli_export(feed, "post", false).unwrap();
+            let html = fs::read_to_string(feed.export_path("post")).unwrap();
+            assert_eq!(html.matches("A unique sentence.").count(), 1, "{html}");
+            let position = |text: &str| html.find(text).unwrap();
+            assert!(position("Top") < position("A unique sentence."));
+            assert!(position("A unique sentence.") < position("Bottom"));
+        });
+    }
+
+    #[test]
+    fn editor_prefers_visual_then_editor() {
+        let env = |vars: &'static [(&'static str, &'static str)]| {
+            move |name: &str| {
+                vars.iter()
+                    .find(|(key, _)| *key == name)
+                    .map(|(_, value)| value.to_string())
+            }
+        };
+        assert_eq!(
+            editor(env(&[("VISUAL", "code --wait"), ("EDITOR", "vim")])),
+            Some("code --wait".to_string())
+        );
+        assert_eq!(editor(env(&[("EDITOR", "vim")])), Some("vim".to_string()));
+        assert_eq!(
+            editor(env(&[("VISUAL", "  "), ("EDITOR", "vim")])),
+            Some("vim".to_string())
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn editor_falls_back_to_one_on_the_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().to_string_lossy().into_owned();
+        let lookup = |path: String| move |name: &str| (name == "PATH").then(|| path.clone());
+
+        assert_eq!(editor(lookup(path.clone())), None);
+        fs::write(dir.path().join("vi"), "").unwrap();
+        assert_eq!(editor(lookup(path.clone())), Some("vi".to_string()));
+        fs::write(dir.path().join("nano"), "").unwrap();
+        assert_eq!(editor(lookup(path)), Some("nano".to_string()));
+    }
+
+    #[test]
+    fn edit_needs_an_existing_document() {
+        in_feed(&[], |feed| {
+            assert_eq!(
+                cli_edit(feed, "missing"),
+                Err("No documents with that name.".to_string())
+            );
+        });
+    }
+
+    #[test]
+    fn remove_deletes_whichever_files_a_document_has() {
+        let files = [
+            ("documents/source-only.md", "# Source only\n"),
+            ("documents/both.md", "# Both\n"),
+            ("export/both.html", "<p>Both</p>"),
+        ];
+        in_feed(&files, |feed| {
+            let (recording, lines) = recording(feed, NORMAL);
+            cli_remove(&recording, "source-only", true).unwrap();
+            assert!(!feed.path("documents/source-only.md").exists());
+            assert_eq!(
+                *lines.borrow(),
+                [
+                    "Deleted source document 'source-only'.",
+                    "No exported document 'source-only' to delete."
+                ]
+            );
+
+            lines.borrow_mut().clear();
+            cli_remove(&recording, "both", true).unwrap();
+            assert!(!feed.path("documents/both.md").exists());
+            assert!(!feed.path("export/both.html").exists());
+            assert_eq!(
+                *lines.borrow(),
+                [
+                    "Deleted source document 'both'.",
+                    "Deleted exported document 'both'."
+                ]
+            );
+        });
+    }
+
+    #[test]
+    fn remove_reports_documents_that_do_not_exist() {
+        in_feed(&[], |feed| {
+            assert_eq!(
+                cli_remove(feed, "missing", true),
+                Err("No documents with that name.".to_string())
+            );
+            assert_eq!(
+                cli_remove(feed, "missing-*", true),
+                Err("No documents match 'missing-*'.".to_string())
+            );
+        });
+    }
+
+    #[test]
+    fn remove_reports_files_it_cannot_delete() {
+        let files = [
+            ("documents/post.md", "# Post\n"),
+            ("export/post.html/index.html", ""),
+        ];
+        in_feed(&files, |feed| {
+            let err = cli_remove(feed, "post", true).unwrap_err();
+            assert!(
+                err.starts_with("Error removing exported document post"),
+                "{err}"
+            );
+            assert!(!feed.path("documents/post.md").exists());
+        });
+    }
+
+    #[test]
+    fn feeds_only_hold_real_posts() {
+        let files = [
+            ("conf.toml", CONF),
+            ("documents/post.md", "# Post\n"),
+            ("documents/.post.md.swp", "swap"),
+            ("documents/.DS_Store", ""),
+            ("documents/.hidden.md", "# Hidden\n"),
+            ("documents/notes.txt", "notes"),
+            ("documents/folder.md/inner.md", "# Inner\n"),
+            ("export/.post.html.swp", "swap"),
+            ("export/images/photo.html", "<p>Not a post</p>"),
+        ];
+        in_feed(&files, |feed| {
+            cli_export_all(feed, false, false).unwrap();
+            cli_atom(feed, false).unwrap();
+            cli_json(feed, false).unwrap();
+
+            let atom = read_atom(feed);
+            let titles = atom
+                .entries()
+                .iter()
+                .map(|entry| entry.title().as_str())
+                .collect::<Vec<_>>();
+            assert_eq!(titles, ["Post"]);
+            let json = fs::read_to_string(feed.path("export/feed.json")).unwrap();
+            let json: serde_json::Value = serde_json::from_str(&json).unwrap();
+            assert_eq!(json["items"].as_array().unwrap().len(), 1);
+        });
+    }
+
+    #[test]
+    fn non_ascii_names_work_everywhere() {
+        let files = [
+            ("conf.toml", CONF),
+            ("documents/café-crème.md", "# Café crème\n"),
+            ("documents/日本語.md", "# 日本語\n"),
+        ];
+        in_feed(&files, |feed| {
+            assert_eq!(
+                document_listing(feed, false).unwrap(),
+                ["café-crème", "日本語"]
+            );
+            cli_export_all(feed, false, false).unwrap();
+            assert!(feed.path("export/café-crème.html").is_file());
+            assert!(feed.path("export/日本語.html").is_file());
+
+            cli_atom(feed, false).unwrap();
+            let mut titles = read_atom(feed)
+                .entries()
+                .iter()
+                .map(|entry| entry.title().to_string())
+                .collect::<Vec<_>>();
+            titles.sort();
+            assert_eq!(titles, ["Café crème", "日本語"]);
+        });
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn exports_with_names_that_are_not_unicode_are_skipped() {
+        use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+        let files = [("conf.toml", CONF), ("documents/post.md", "# Post\n")];
+        in_feed(&files, |feed| {
+            cli_export(feed, "post", false).unwrap();
+            let odd = feed
+                .path("export")
+                .join(OsStr::from_bytes(b"odd-\xff.html"));
+            fs::write(odd, "<p>Odd</p>").unwrap();
+            cli_atom(feed, false).unwrap();
+            assert_eq!(read_atom(feed).entries().len(), 1);
+            assert!(!fs::read_to_string(feed.path("export/feed.xml"))
+                .unwrap()
+                .contains('\u{FFFD}'));
+        });
+    }
+
+    #[test]
+    fn export_writes_fresh_files_and_only_overwrites_when_forced() {
+        let files = [
+            ("conf.toml", CONF),
+            ("documents/new.md", "# New\n"),
+            ("documents/tweaked.md", "# Tweaked\n"),
+            ("export/tweaked.html", "hand edited"),
+        ];
+        in_feed(&files, |feed| {
+            cli_export(feed, "new", false).unwrap();
+            assert!(fs::read_to_string(feed.export_path("new"))
+                .unwrap()
+                .contains("New"));
+
+            let err = cli_export(feed, "tweaked", false).unwrap_err();
+            assert!(
+                err.ends_with("already exists. Use --force to overwrite it."),
+                "{err}"
+            );
+            assert_eq!(
+                fs::read_to_string(feed.export_path("tweaked")).unwrap(),
+                "hand edited"
+            );
+
+            cli_export(feed, "tweaked", true).unwrap();
+            assert!(fs::read_to_string(feed.export_path("tweaked"))
+                .unwrap()
+                .contains("Tweaked"));
+        });
+    }
+
+    #[test]
+    fn export_all_skips_existing_exports_unless_forced() {
+        let files = [
+            ("conf.toml", CONF),
+            ("documents/one.md", "# One\n"),
+            ("documents/two.md", "# Two\n"),
+            ("export/one.html", "hand edited"),
+        ];
+        in_feed(&files, |feed| {
+            let (recording, lines) = recording(feed, NORMAL);
+            cli_export_all(&recording, false, false).unwrap();
+            assert_eq!(
+                fs::read_to_string(feed.export_path("one")).unwrap(),
+                "hand edited"
+            );
+            assert!(feed.export_path("two").exists());
+            assert!(lines
+                .borrow()
+                .contains(&"Use --force to overwrite existing exports.".to_string()));
+
+            cli_export_all(feed, false, true).unwrap();
+            assert!(fs::read_to_string(feed.export_path("one"))
+                .unwrap()
+                .contains("One"));
+        });
+    }
+
+    #[test]
+    fn feeds_without_exports_are_empty_but_valid() {
+        in_feed(&[("conf.toml", CONF)], |feed| {
+            cli_atom(feed, false).unwrap();
+            assert!(read_atom(feed).entries().is_empty());
+
+            cli_json(feed, false).unwrap();
+            let json = fs::read_to_string(feed.path("export/feed.json")).unwrap();
+            let json: serde_json::Value = serde_json::from_str(&json).unwrap();
+            assert_eq!(json["items"], serde_json::json!([]));
+        });
+    }
+
+    #[test]
+    fn failed_commands_are_errors_and_successful_ones_are_not() {
+        in_feed(&[("documents/post.md", "# Post\n")], |feed| {
+            let run = |command: &[&str]| {
+                let mut args = vec!["feed".to_string()];
+                args.extend(command.iter().map(|arg| arg.to_string()));
+                args.extend(["--dir".to_string(), feed.root.to_string_lossy().to_string()]);
+                process(args)
+            };
+
+            // There is no conf.toml yet, so exporting and checking the configuration fail
+            assert!(run(&["export", "post", "-q"]).is_err());
+            assert!(run(&["validate", "-q"]).is_err());
+            assert!(run(&["export", "missing", "-q"]).is_err());
+            assert!(run(&["export", "../post", "-q"]).is_err());
+            assert_eq!(
+                run(&["frobnicate"]),
+                Err("Unknown command 'frobnicate'.".to_string())
+            );
+
+            fs::write(feed.path("conf.toml"), CONF).unwrap();
+            assert_eq!(run(&["export", "post", "-q"]), Ok(()));
+            assert_eq!(run(&["validate", "-q"]), Ok(()));
+            assert!(run(&["export", "post", "-q"]).is_err());
+        });
+    }
+
+    #[test]
+    fn robots_allows_everything_by_default() {
+        in_feed(&[("conf.toml", "title = \"Site\"\n")], |feed| {
+            cli_robots(feed).unwrap();
+            assert_eq!(
+                fs::read_to_string(feed.path("export/robots.txt")).unwrap(),
+                "User-agent: *\nDisallow:\n"
+            );
+        });
+    }
+
+    #[test]
+    fn robots_lists_the_rules_and_links_the_sitemap() {
+        let conf = r#"
+base_url = "https://example.com/blog/"
+
+[[robots]]
+user_agent = "*"
+disallow = ["/drafts/", "/private/"]
+
+[[robots]]
+user_agent = "GoodBot"
+allow = ["/"]
+"#;
+        in_feed(&[("conf.toml", conf)], |feed| {
+            cli_robots(feed).unwrap();
+            assert_eq!(
+                fs::read_to_string(feed.path("export/robots.txt")).unwrap(),
+                "User-agent: *\nDisallow: /drafts/\nDisallow: /private/\n\n\
+                 User-agent: GoodBot\nAllow: /\n\n\
+                 Sitemap: https://example.com/blog/sitemap.xml\n"
+            );
+        });
+    }
+
+    #[test]
+    fn exports_carry_opengraph_tags() {
+        let post = "---\ntitle: Rock & Roll\ndescription: A \"loud\" post\nimage: images/cover.png\n---\nThe body.\n";
+        let files = [("conf.toml", CONF), ("documents/post.md", post)];
+        in_feed(&files, |feed| {
+            cli_export(feed, "post", false).unwrap();
+            let html = fs::read_to_string(feed.export_path("post")).unwrap();
+            for (property, content) in [
+                ("og:type", "article"),
+                ("og:title", "Rock &amp; Roll"),
+                ("og:description", "A &quot;loud&quot; post"),
+                ("og:url", "https://example.com/post.html"),
+                ("og:image", "https://example.com/images/cover.png"),
+            ] {
+                let tag = format!("<meta property=\"{property}\" content=\"{content}\">");
+                assert!(html.contains(&tag), "{tag} in {html}");
+            }
+        });
+    }
+
+    #[test]
+    fn opengraph_tags_are_left_out_without_their_data() {
+        let files = [
+            ("conf.toml", "title = \"Site\"\n"),
+            ("documents/post.md", "The body.\n"),
+        ];
+        in_feed(&files, |feed| {
+            cli_export(feed, "post", false).unwrap();
+            let html = fs::read_to_string(feed.export_path("post")).unwrap();
+            assert!(html.contains("<meta property=\"og:type\" content=\"article\">"));
+            for property in ["og:title", "og:description", "og:url", "og:image"] {
+                assert!(!html.contains(property), "{property} in {html}");
+            }
+        });
+    }
+
+    #[test]
+    fn twitter_cards_grow_when_there_is_an_image() {
+        let conf = format!("twitter_handle = \"@site\"\n{CONF}");
+        let files = [
+            ("conf.toml", conf.as_str()),
+            ("documents/plain.md", "# Plain\n"),
+            (
+                "documents/pictured.md",
+                "---\nimage: cover.png\n---\n# Pictured\n",
+            ),
+        ];
+        in_feed(&files, |feed| {
+            cli_export_all(feed, false, false).unwrap();
+            let tag = |document: &str, name: &str, content: &str| {
+                let html = fs::read_to_string(feed.export_path(document)).unwrap();
+                html.contains(&format!("<meta name=\"{name}\" content=\"{content}\">"))
+            };
+
+            assert!(tag("plain", "twitter:card", "summary"));
+            assert!(tag("plain", "twitter:title", "Plain"));
+            assert!(tag("plain", "twitter:site", "@site"));
+            assert!(!tag(
+                "plain",
+                "twitter:image",
+                "https://example.com/cover.png"
+            ));
+
+            assert!(tag("pictured", "twitter:card", "summary_large_image"));
+            assert!(tag(
+                "pictured",
+                "twitter:image",
+                "https://example.com/cover.png"
+            ));
+            assert!(tag("pictured", "twitter:site", "@site"));
+        });
+    }
+
+    #[test]
+    fn dated_exports_embed_article_structured_data() {
+        let conf = format!("author = \"me@example.com (Jo Bloggs)\"\n{CONF}");
+        let post = "---\ntitle: Ending </script> early\ndate: 2024-05-01\n---\nThe body.\n";
+        let files = [("conf.toml", conf.as_str()), ("documents/post.md", post)];
+        in_feed(&files, |feed| {
+            cli_export(feed, "post", false).unwrap();
+            let html = fs::read_to_string(feed.export_path("post")).unwrap();
+            let start = "<script type=\"application/ld+json\">\n";
+            let data = &html[html.find(start).unwrap() + start.len()..];
+            let data = &data[..data.find("\n</script>").unwrap()];
+            assert!(!data.contains("</script>"), "{data}");
+
+            let article: serde_json::Value = serde_json::from_str(data).unwrap();
+            assert_eq!(article["@type"], "Article");
+            assert_eq!(article["headline"], "Ending </script> early");
+            assert_eq!(article["datePublished"], "2024-05-01");
+            assert!(article["dateModified"].is_string());
+            assert_eq!(article["url"], "https://example.com/post.html");
+            assert_eq!(article["author"]["name"], "Jo Bloggs");
+            assert_eq!(article["publisher"]["name"], "Site");
+        });
+    }
+
+    #[test]
+    fn structured_data_needs_a_title_and_a_date() {
+        let files = [
+            ("conf.toml", CONF),
+            ("documents/undated.md", "# Undated\n"),
+            (
+                "documents/untitled.md",
+                "---\ndate: 2024-05-01\n---\nNo heading.\n",
+            ),
+        ];
+        in_feed(&files, |feed| {
+            cli_export_all(feed, false, false).unwrap();
+            for document in ["undated", "untitled"] {
+                let html = fs::read_to_string(feed.export_path(document)).unwrap();
+                assert!(!html.contains("application/ld+json"), "{document}: {html}");
+            }
+        });
+    }
+
+    #[test]
+    fn parse_date_accepts_days_and_timestamps() {
+        assert_eq!(
+            parse_date("2024-05-01").unwrap().to_rfc3339(),
+            "2024-05-01T00:00:00+00:00"
+        );
+        assert_eq!(
+            parse_date("2024-05-01T12:30:00+02:00")
+                .unwrap()
+                .to_rfc3339(),
+            "2024-05-01T10:30:00+00:00"
+        );
+        assert!(parse_date("May 1st").is_none());
+    }
+
+    #[test]
+    fn feed_entries_are_dated_and_sorted_by_front_matter() {
+        let files = [
+            ("conf.toml", CONF),
+            ("documents/old.md", "---\ndate: 2020-01-01\n---\n# Old\n"),
+            ("documents/new.md", "---\ndate: 2023-06-15\n---\n# New\n"),
+        ];
+        in_feed(&files, |feed| {
+            // Exporting the older post last must not make it the newest entry
+            cli_export(feed, "new", false).unwrap();
+            cli_export(feed, "old", false).unwrap();
+
+            let pages = collect_pages(feed, &export_conf(feed).unwrap(), false).unwrap();
+            let slugs = pages
+                .iter()
+                .map(|page| page.slug.as_str())
+                .collect::<Vec<_>>();
+            assert_eq!(slugs, ["new", "old"]);
+            assert_eq!(pages[1].published, parse_date("2020-01-01"));
+            assert_eq!(pages[1].updated, parse_date("2020-01-01").unwrap());
+        });
+    }
+
+    #[test]
+    fn meta_property_decodes_escaped_content() {
+        let html = r#"<head><meta property="og:title" content="Rock &amp; Roll&#39;s"></head>"#;
+        assert_eq!(
+            meta_property(html, "og:title").as_deref(),
+            Some("Rock & Roll's")
+        );
+    }
+
+    #[test]
+    fn attribute_reads_minified_values() {
+        assert_eq!(
+            attribute("property=og:title content='Rock &amp; Roll'", "content").as_deref(),
+            Some("Rock & Roll")
+        );
+        assert_eq!(
+            attribute("property=og:title content=Rock", "content").as_deref(),
+            Some("Rock")
+        );
+    }
+
+    #[test]
+    fn preview_title_prefers_front_matter_then_heading() {
+        let conf = Conf::default();
+        let front_matter = FrontMatter {
+            title: Some("Rock & Roll's".to_string()),
+            ..Default::default()
+        };
+        let title = preview("rock", &front_matter, "# Heading\n", &conf).title;
+        assert_eq!(title.as_deref(), Some("Rock & Roll's"));
+
+        let title = preview("rock", &FrontMatter::default(), "# Rock & Roll's\n", &conf).title;
+        assert_eq!(title.as_deref(), Some("Rock & Roll's"));
     }
 }
@@ -1,6 +1,11 @@
 use crate::config::toml::Conf;
 use core::fmt;
-use std::{fs, io::Read, str::from_utf8};
+use std::{
+    env, fs,
+    io::{self, Read},
+    path::{Path, PathBuf},
+    str::from_utf8,
+};
 
 // Function to read a file from a directory and return its content as a string
 pub fn fs_to_str(directory: &str) -> String {
@@ -13,7 +18,80 @@ pub fn fs_to_str(directory: &str) -> String {
 
 // Function to write a string content to a file in a directory
 pub fn str_to_fs(directory: &str, content: &str) {
-    fs::write(directory, content).expect("Failed to write to file.");
+    write_atomic(directory, content).expect("Failed to write to file.");
+}
+
+// Function to write a file through a hidden temporary file beside it, so readers never see partial output
+pub fn write_atomic(path: impl AsRef<Path>, content: &str) -> io::Result<()> {
+    let path = path.as_ref();
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    let temporary = path.with_file_name(format!(".{file_name}.tmp"));
+
+    fs::write(&temporary, content)
+        .and_then(|_| fs::rename(&temporary, path))
+        .inspect_err(|_| {
+            let _ = fs::remove_file(&temporary);
+        })
+}
+
+// Function to end content with exactly one newline so written files play well with other tools
+pub fn with_trailing_newline(content: &str) -> String {
+    format!("{}\n", content.trim_end_matches(['\n', '\r']))
+}
+
+// Function to guess the MIME type of a file from its extension
+pub fn mime_type(path: &str) -> &'static str {
+    let extension = Path::new(path)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or_default()
+        .to_lowercase();
+
+    match extension.as_str() {
+        "html" | "htm" => "text/html",
+        "css" => "text/css",
+        "js" | "mjs" => "text/javascript",
+        "json" => "application/json",
+        "xml" => "application/xml",
+        "txt" => "text/plain",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "avif" => "image/avif",
+        "svg" => "image/svg+xml",
+        "ico" => "image/x-icon",
+        "mp3" => "audio/mpeg",
+        "m4a" => "audio/mp4",
+        "ogg" | "oga" => "audio/ogg",
+        "opus" => "audio/opus",
+        "flac" => "audio/flac",
+        "wav" => "audio/wav",
+        "mp4" | "m4v" => "video/mp4",
+        "webm" => "video/webm",
+        "mov" => "video/quicktime",
+        "pdf" => "application/pdf",
+        "epub" => "application/epub+zip",
+        "zip" => "application/zip",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        _ => "application/octet-stream",
+    }
+}
+
+// Function to give the Content-Type a file is served with, marking text as UTF-8 as Adduce writes it
+pub fn content_type(path: &str) -> String {
+    let mime = mime_type(path);
+    let is_text = mime.starts_with("text/")
+        || matches!(
+            mime,
+            "application/json" | "application/xml" | "image/svg+xml"
+        );
+    if is_text {
+        format!("{mime}; charset=utf-8")
+    } else {
+        mime.to_string()
+    }
 }
 
 // Custom error type for importing configs
@@ -25,6 +103,7 @@ pub struct ConfError(CError);
 pub enum CError {
     File(String, String),
     Toml(String, String),
+    Include(String, String),
 }
 
 // Implement Display trait for ConfError to provide custom error messages
@@ -33,6 +112,7 @@ impl fmt::Display for ConfError {
         let msg = match &self.0 {
             CError::File(error, page) => format!("File error on page {page}: {error}"),
             CError::Toml(error, page) => format!("TOML error on page {page}: {error}"),
+            CError::Include(error, page) => format!("Configuration error on page {page}: {error}"),
         };
         write!(f, "{msg}")
     }
@@ -48,6 +128,262 @@ pub fn import_conf(directory: &str) -> Result<Conf> {
         .map_err(|e| ConfError(CError::File(e.to_string(), directory.to_string())))?;
     file.read_to_string(&mut content)
         .map_err(|e| ConfError(CError::File(e.to_string(), directory.to_string())))?;
-    toml::from_str::<Conf>(&content)
-        .map_err(|e| ConfError(CError::Toml(e.to_string(), directory.to_string())))
+    let conf = toml::from_str::<Conf>(&content)
+        .map_err(|e| ConfError(CError::Toml(e.to_string(), directory.to_string())))?;
+    resolve_conf(conf, directory).map_err(|e| ConfError(CError::Include(e, directory.to_string())))
+}
+
+// Function to finish loading a configuration read from a path by merging its includes and substituting environment variables
+pub fn resolve_conf(conf: Conf, path: impl AsRef<Path>) -> std::result::Result<Conf, String> {
+    let path = path.as_ref();
+    let root = path.parent().unwrap_or(Path::new(""));
+    let mut stack = path.canonicalize().into_iter().collect();
+    let conf = merge_includes(conf, root, &mut stack)?;
+    substitute_env(conf, |name| env::var(name).ok())
+}
+
+// Function to place the blocks of included configuration files before a configuration's own blocks
+fn merge_includes(
+    mut conf: Conf,
+    root: &Path,
+    stack: &mut Vec<PathBuf>,
+) -> std::result::Result<Conf, String> {
+    let Some(includes) = conf.include.take() else {
+        return Ok(conf);
+    };
+
+    // Every include is resolved against the feed root, however deeply it is nested
+    let mut blocks = Vec::new();
+
+    for include in includes {
+        let include_path = root.join(&include);
+        let canonical = include_path
+            .canonicalize()
+            .map_err(|e| format!("could not read {}: {e}", include_path.display()))?;
+        if stack.contains(&canonical) {
+            return Err(format!("circular include of {}", include_path.display()));
+        }
+
+        let content = fs::read_to_string(&include_path)
+            .map_err(|e| format!("could not read {}: {e}", include_path.display()))?;
+        let partial = toml::from_str::<Conf>(&content)
+            .map_err(|e| format!("could not parse {}: {e}", include_path.display()))?;
+
+        stack.push(canonical);
+        let partial = merge_includes(partial, root, stack)?;
+        stack.pop();
+
+        blocks.extend(partial.main.map(|main| main.block).unwrap_or_default());
+    }
+
+    let main = conf.main.get_or_insert_with(Default::default);
+    blocks.append(&mut main.block);
+    main.block = blocks;
+    Ok(conf)
+}
+
+// Function to replace `${VAR}` references in a configuration's metadata with environment variables
+fn substitute_env(
+    mut conf: Conf,
+    lookup: impl Fn(&str) -> Option<String>,
+) -> std::result::Result<Conf, String> {
+    let fields = [
+        &mut conf.title,
+        &mut conf.id,
+        &mut conf.icon,
+        &mut conf.logo,
+        &mut conf.rights,
+        &mut conf.author,
+        &mut conf.subtitle,
+        &mut conf.base,
+        &mut conf.lang,
+        &mut conf.base_url,
+        &mut conf.feed_url,
+    ];
+    for value in fields.into_iter().flatten() {
+        *value = expand_env(value, &lookup)?;
+    }
+    Ok(conf)
+}
+
+// Function to expand `${VAR}` references in a string, with `$$` escaping a literal `$`
+fn expand_env(
+    value: &str,
+    lookup: impl Fn(&str) -> Option<String>,
+) -> std::result::Result<String, String> {
+    let mut expanded = String::with_capacity(value.len());
+    let mut rest = value;
+
+    while let Some(position) = rest.find('$') {
+        expanded.push_str(&rest[..position]);
+        rest = &rest[position + 1..];
+
+        if let Some(after) = rest.strip_prefix('$') {
+            expanded.push('$');
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix('{') {
+            let end = after
+                .find('}')
+                .ok_or_else(|| format!("unclosed variable reference in '{value}'"))?;
+            let name = &after[..end];
+            let variable =
+                lookup(name).ok_or_else(|| format!("environment variable {name} is not set"))?;
+            expanded.push_str(&variable);
+            rest = &after[end + 1..];
+        } else {
+            expanded.push('$');
+        }
+    }
+
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn content_type_gives_text_a_charset() {
+        assert_eq!(content_type("index.html"), "text/html; charset=utf-8");
+        assert_eq!(content_type("feed.json"), "application/json; charset=utf-8");
+        assert_eq!(content_type("logo.svg"), "image/svg+xml; charset=utf-8");
+        assert_eq!(content_type("photo.JPG"), "image/jpeg");
+        assert_eq!(content_type("archive"), "application/octet-stream");
+    }
+
+    // Look variables up in a fixed set rather than the process environment
+    fn lookup(name: &str) -> Option<String> {
+        match name {
+            "ADDUCE_TEST_EXPAND" => Some("value".to_string()),
+            "ADDUCE_TEST_SITE_URL" => Some("https://ci.example.com/".to_string()),
+            "ADDUCE_TEST_AUTHOR" => Some("me@example.com (Me)".to_string()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn expand_env_substitutes_and_escapes() {
+        assert_eq!(
+            expand_env("a ${ADDUCE_TEST_EXPAND} $$5 $x", lookup).unwrap(),
+            "a value $5 $x"
+        );
+        assert!(expand_env("${ADDUCE_TEST_UNSET}", lookup)
+            .unwrap_err()
+            .contains("ADDUCE_TEST_UNSET"));
+        assert!(expand_env("${ADDUCE_TEST_EXPAND", lookup)
+            .unwrap_err()
+            .contains("unclosed"));
+    }
+
+    #[test]
+    fn substitute_env_covers_base_url_and_author() {
+        let conf = Conf {
+            base_url: Some("${ADDUCE_TEST_SITE_URL}".to_string()),
+            author: Some("${ADDUCE_TEST_AUTHOR}".to_string()),
+            ..Default::default()
+        };
+        let conf = substitute_env(conf, lookup).unwrap();
+        assert_eq!(conf.base_url.as_deref(), Some("https://ci.example.com/"));
+        assert_eq!(conf.author.as_deref(), Some("me@example.com (Me)"));
+    }
+
+    // Write the given files into a new directory and load its top-level conf.toml
+    fn load(files: &[(&str, &str)]) -> std::result::Result<Conf, String> {
+        let dir = tempfile::tempdir().unwrap();
+        for (path, content) in files {
+            let path = dir.path().join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+        let path = dir.path().join("conf.toml");
+        let conf = toml::from_str::<Conf>(&fs::read_to_string(&path).unwrap()).unwrap();
+        resolve_conf(conf, path.to_str().unwrap())
+    }
+
+    fn contents(conf: &Conf) -> Vec<&str> {
+        conf.main
+            .iter()
+            .flat_map(|main| &main.block)
+            .filter_map(|obj| obj.content.as_deref())
+            .collect()
+    }
+
+    #[test]
+    fn includes_come_before_own_blocks() {
+        let conf = load(&[
+            (
+                "conf.toml",
+                "include = [\"partials/header.toml\"]\n[[main.block]]\ncontent = \"own\"\n",
+            ),
+            (
+                "partials/header.toml",
+                "[[main.block]]\ncontent = \"header\"\n",
+            ),
+        ])
+        .unwrap();
+        assert_eq!(contents(&conf), ["header", "own"]);
+    }
+
+    #[test]
+    fn nested_includes_resolve_against_the_feed_root() {
+        let conf = load(&[
+            ("conf.toml", "include = [\"partials/outer.toml\"]\n"),
+            (
+                "partials/outer.toml",
+                "include = [\"partials/inner.toml\"]\n[[main.block]]\ncontent = \"outer\"\n",
+            ),
+            (
+                "partials/inner.toml",
+                "[[main.block]]\ncontent = \"inner\"\n",
+            ),
+        ])
+        .unwrap();
+        assert_eq!(contents(&conf), ["inner", "outer"]);
+    }
+
+    #[test]
+    fn circular_includes_are_rejected() {
+        let err = load(&[
+            ("conf.toml", "include = [\"partials/a.toml\"]\n"),
+            ("partials/a.toml", "include = [\"partials/b.toml\"]\n"),
+            ("partials/b.toml", "include = [\"partials/a.toml\"]\n"),
+        ])
+        .unwrap_err();
+        assert!(err.contains("circular include"), "{err}");
+    }
+
+    #[test]
+    fn with_trailing_newline_ends_with_exactly_one_newline() {
+        assert_eq!(with_trailing_newline("<p>Hi</p>"), "<p>Hi</p>\n");
+        assert_eq!(with_trailing_newline("<p>Hi</p>\r\n\n\n"), "<p>Hi</p>\n");
+        assert_eq!(with_trailing_newline(""), "\n");
+    }
+
+    #[test]
+    fn write_atomic_replaces_the_file_and_cleans_up() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("page.html");
+        fs::write(&path, "old and much longer content").unwrap();
+
+        write_atomic(&path, "new").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new");
+        let names = fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["page.html"]);
+    }
+
+    #[test]
+    fn write_atomic_leaves_nothing_behind_when_it_fails() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("page.html");
+        fs::create_dir(&path).unwrap();
+        fs::write(path.join("index.html"), "kept").unwrap();
+
+        assert!(write_atomic(&path, "new").is_err());
+        assert!(!dir.path().join(".page.html.tmp").exists());
+        assert_eq!(fs::read_to_string(path.join("index.html")).unwrap(), "kept");
+    }
 }
@@ -0,0 +1,233 @@
+use crate::config::toml::FrontMatter;
+use core::fmt;
+use std::io::{self, BufRead};
+
+// Delimiters fencing TOML and YAML front matter blocks
+const TOML_DELIMITER: &str = "+++";
+const YAML_DELIMITER: &str = "---";
+
+// Error type for front matter that could not be parsed
+#[derive(Debug)]
+pub enum FrontMatterError {
+    Toml(toml::de::Error),
+    Yaml(serde_yaml::Error),
+}
+
+// Implement Display trait for FrontMatterError to name the format that failed
+impl fmt::Display for FrontMatterError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FrontMatterError::Toml(error) => write!(f, "invalid TOML front matter: {error}"),
+            FrontMatterError::Yaml(error) => write!(f, "invalid YAML front matter: {error}"),
+        }
+    }
+}
+
+// Split a document into its front matter and the remaining body
+pub fn split(content: &str) -> Result<(FrontMatter, &str), FrontMatterError> {
+    if let Some((front_matter, body)) = delimited(content, TOML_DELIMITER) {
+        let front_matter = toml::from_str(front_matter).map_err(FrontMatterError::Toml)?;
+        return Ok((front_matter, body));
+    }
+
+    if let Some((front_matter, body)) = delimited(content, YAML_DELIMITER) {
+        // An empty YAML block is valid but does not deserialize into a struct
+        if front_matter.trim().is_empty() {
+            return Ok((FrontMatter::default(), body));
+        }
+        let front_matter = serde_yaml::from_str(front_matter).map_err(FrontMatterError::Yaml)?;
+        return Ok((front_matter, body));
+    }
+
+    Ok((FrontMatter::default(), content))
+}
+
+// Read a document's front matter and the start of its body, stopping at a line containing the marker or once the body reaches the limit
+pub fn read_opening(mut reader: impl BufRead, marker: &str, limit: usize) -> io::Result<String> {
+    let mut content = String::new();
+    let mut line = String::new();
+    let mut delimiter = None;
+    let mut body_start = None;
+
+    while reader.read_line(&mut line)? > 0 {
+        content += &line;
+        match (body_start, delimiter) {
+            // The first line decides whether the document opens with front matter
+            (None, None) if content.len() == line.len() => {
+                delimiter = [TOML_DELIMITER, YAML_DELIMITER]
+                    .into_iter()
+                    .find(|delimiter| line.trim_end() == *delimiter);
+                if delimiter.is_none() {
+                    body_start = Some(0);
+                }
+            }
+            (None, Some(delimiter)) if line.trim_end() == delimiter => {
+                body_start = Some(content.len());
+            }
+            _ => {}
+        }
+
+        if let Some(start) = body_start {
+            if line.contains(marker) || content.len() - start >= limit {
+                break;
+            }
+        }
+        line.clear();
+    }
+    Ok(content)
+}
+
+// Add a date to a document's front matter, creating a YAML block if it has none
+pub fn stamp_date(content: &str, date: &str) -> String {
+    // Leave dates that are already set, such as those from a template, alone
+    if split(content).is_ok_and(|(front_matter, _)| front_matter.date.is_some()) {
+        return content.to_string();
+    }
+
+    if let Some((front_matter, body)) = delimited(content, TOML_DELIMITER) {
+        return format!(
+            "{TOML_DELIMITER}\ndate = \"{date}\"\n{front_matter}{TOML_DELIMITER}\n{body}"
+        );
+    }
+    if let Some((front_matter, body)) = delimited(content, YAML_DELIMITER) {
+        return format!("{YAML_DELIMITER}\ndate: {date}\n{front_matter}{YAML_DELIMITER}\n{body}");
+    }
+    format!("{YAML_DELIMITER}\ndate: {date}\n{YAML_DELIMITER}\n{content}")
+}
+
+// Remove the date from a document's front matter
+pub fn strip_date(content: &str) -> String {
+    for delimiter in [TOML_DELIMITER, YAML_DELIMITER] {
+        if let Some((front_matter, body)) = delimited(content, delimiter) {
+            let front_matter = front_matter
+                .split_inclusive('\n')
+                .filter(|line| {
+                    let key = line.split(['=', ':']).next().unwrap_or_default();
+                    key.trim() != "date"
+                })
+                .collect::<String>();
+            if front_matter.trim().is_empty() {
+                return body.to_string();
+            }
+            return format!("{delimiter}\n{front_matter}{delimiter}\n{body}");
+        }
+    }
+    content.to_string()
+}
+
+// Find a block fenced by the delimiter at the very start of a document
+fn delimited<'a>(content: &'a str, delimiter: &str) -> Option<(&'a str, &'a str)> {
+    let rest = content.strip_prefix(delimiter)?;
+    let rest = rest
+        .strip_prefix("\r\n")
+        .or_else(|| rest.strip_prefix('\n'))?;
+
+    let mut offset = 0;
+    for line in rest.split_inclusive('\n') {
+        if line.trim_end() == delimiter {
+            return Some((&rest[..offset], &rest[offset + line.len()..]));
+        }
+        offset += line.len();
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_reads_yaml_front_matter() {
+        let (front_matter, body) = split("---\ntitle: Hello\ndraft: true\n---\n# Body\n").unwrap();
+        assert_eq!(front_matter.title.as_deref(), Some("Hello"));
+        assert_eq!(front_matter.draft, Some(true));
+        assert_eq!(body, "# Body\n");
+    }
+
+    #[test]
+    fn split_reads_toml_front_matter() {
+        let (front_matter, body) = split("+++\ntitle = \"Hello\"\n+++\n# Body\n").unwrap();
+        assert_eq!(front_matter.title.as_deref(), Some("Hello"));
+        assert_eq!(body, "# Body\n");
+    }
+
+    #[test]
+    fn split_leaves_documents_without_front_matter_whole() {
+        let content = "# Body\n\n---\n\nAfter a rule.\n";
+        let (front_matter, body) = split(content).unwrap();
+        assert!(front_matter.title.is_none());
+        assert_eq!(body, content);
+
+        let (_, body) = split("---\n---\nBody\n").unwrap();
+        assert_eq!(body, "Body\n");
+    }
+
+    #[test]
+    fn split_names_the_format_of_malformed_front_matter() {
+        let error = split("---\ntitle: [unclosed\n---\n").unwrap_err();
+        assert!(error.to_string().starts_with("invalid YAML front matter"));
+        let error = split("+++\ntitle = \n+++\n").unwrap_err();
+        assert!(error.to_string().starts_with("invalid TOML front matter"));
+    }
+
+    #[test]
+    fn stamp_date_adds_a_date_in_the_documents_format() {
+        assert_eq!(
+            stamp_date("# Body\n", "2024-05-01"),
+            "---\ndate: 2024-05-01\n---\n# Body\n"
+        );
+        assert_eq!(
+            stamp_date("---\ntitle: Hi\n---\nBody\n", "2024-05-01"),
+            "---\ndate: 2024-05-01\ntitle: Hi\n---\nBody\n"
+        );
+        assert_eq!(
+            stamp_date("+++\ntitle = \"Hi\"\n+++\nBody\n", "2024-05-01"),
+            "+++\ndate = \"2024-05-01\"\ntitle = \"Hi\"\n+++\nBody\n"
+        );
+    }
+
+    #[test]
+    fn stamp_date_keeps_an_existing_date() {
+        let content = "---\ndate: 2020-01-01\n---\nBody\n";
+        assert_eq!(stamp_date(content, "2024-05-01"), content);
+    }
+
+    #[test]
+    fn strip_date_removes_only_the_date() {
+        assert_eq!(
+            strip_date("---\ntitle: Hi\ndate: 2024-05-01\n---\nBody\n"),
+            "---\ntitle: Hi\n---\nBody\n"
+        );
+        assert_eq!(
+            strip_date("+++\ndate = \"2024-05-01\"\ndraft = true\n+++\nBody\n"),
+            "+++\ndraft = true\n+++\nBody\n"
+        );
+        assert_eq!(strip_date("# No front matter\n"), "# No front matter\n");
+    }
+
+    #[test]
+    fn strip_date_drops_a_block_left_empty() {
+        assert_eq!(strip_date("---\ndate: 2024-05-01\n---\nBody\n"), "Body\n");
+    }
+
+    #[test]
+    fn read_opening_stops_at_the_marker_after_the_front_matter() {
+        let content = "---\ntitle: <!-- more -->\n---\nFirst\n<!-- more -->\nRest\n";
+        let opening = read_opening(content.as_bytes(), "<!-- more -->", 1024).unwrap();
+        assert_eq!(
+            opening,
+            "---\ntitle: <!-- more -->\n---\nFirst\n<!-- more -->\n"
+        );
+    }
+
+    #[test]
+    fn read_opening_limits_only_the_body() {
+        let front_matter = format!("---\ndescription: {}\n---\n", "x".repeat(100));
+        let content = format!("{front_matter}one\ntwo\nthree\n");
+        let opening = read_opening(content.as_bytes(), "<!-- more -->", 5).unwrap();
+        assert_eq!(opening, format!("{front_matter}one\ntwo\n"));
+
+        let opening = read_opening("one\ntwo\n".as_bytes(), "<!-- more -->", 1024).unwrap();
+        assert_eq!(opening, "one\ntwo\n");
+    }
+}
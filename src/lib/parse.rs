@@ -1,65 +1,1088 @@
 use crate::config::toml::{Conf, Object};
-use crate::lib::filesystem::fs_to_str;
-use pulldown_cmark::{html, Options, Parser};
+use crate::lib::filesystem::{fs_to_str, mime_type};
+use chrono::{Datelike, Local};
+use pulldown_cmark::{html, CodeBlockKind, Event, HeadingLevel, Options, Parser, Tag, TagEnd};
+use regex::{Captures, Regex};
+use std::{collections::HashMap, path::PathBuf, sync::OnceLock};
+use syntect::{
+    easy::HighlightLines,
+    highlighting::{Theme, ThemeSet},
+    html::{
+        highlighted_html_for_string, start_highlighted_html_snippet,
+        styled_line_to_highlighted_html, IncludeBackground,
+    },
+    parsing::{SyntaxReference, SyntaxSet},
+    util::LinesWithEndings,
+};
+
+/// Stylesheets built into the binary that can be selected with the theme field
+const THEMES: &[(&str, &str)] = &[
+    ("minimal", include_str!("../themes/minimal.css")),
+    ("dark", include_str!("../themes/dark.css")),
+    ("serif", include_str!("../themes/serif.css")),
+];
 
 impl Conf {
-    pub fn to_html(&self) -> String {
-        let mut divs = String::new();
+    /// Render the page, giving it a head only when there is something to put in one
+    pub fn to_html(&self) -> Result<String, String> {
+        let body = self.body_html()?;
+        let head = self.head_html(false)?;
+        let html = if head.is_empty() {
+            body
+        } else {
+            format!("<head>\n{head}</head>\n<body>\n{body}\n</body>")
+        };
+        Ok(self.minify_if_enabled(html))
+    }
+
+    /// Render a complete HTML document, titled and described for the page and in its language
+    pub fn to_document(&self) -> Result<String, String> {
+        let body = self.body_html()?;
+        let head = self.head_html(true)?;
+        let lang = self.lang.as_ref().map_or(String::new(), |lang| {
+            format!(" lang=\"{}\"", escape_html(lang))
+        });
+        let html = format!(
+            "<!DOCTYPE html>\n<html{lang}>\n<head>\n<meta charset=\"utf-8\">\n{head}</head>\n<body>\n{body}\n</body>\n</html>"
+        );
+        Ok(self.minify_if_enabled(html))
+    }
+
+    /// Minify the rendered page if the configuration asks for it
+    fn minify_if_enabled(&self, html: String) -> String {
+        if self.minify.unwrap_or(false) {
+            minify(&html)
+        } else {
+            html
+        }
+    }
+
+    /// Render everything that goes in the body of the page
+    fn body_html(&self) -> Result<String, String> {
+        let mut divs = self.nav_html();
         let blocks = self.main.clone().unwrap_or_default().block;
-        for (i, obj) in blocks.iter().enumerate() {
-            let html = compile_html(obj);
-            if i == blocks.len() - 1 {
-                divs += &html.trim_end_matches('\n'); // Avoid trailing newline for the last element
+        for obj in &blocks {
+            divs += &compile_html(obj, self)?;
+        }
+        if let Some(footer) = &self.footer {
+            let footer = footer.replace("{{year}}", &Local::now().year().to_string());
+            divs += &format!(
+                "<footer>\n{}</footer>\n",
+                convert_markdown_to_html(&footer, self)
+            );
+        }
+        for script in self.scripts.as_deref().unwrap_or_default() {
+            let defer = if script.defer.unwrap_or(false) {
+                " defer"
+            } else {
+                ""
+            };
+            divs += &format!(
+                "<script src=\"{}\"{defer}></script>\n",
+                escape_html(&script.src)
+            );
+        }
+        divs.truncate(divs.trim_end_matches('\n').len()); // Avoid trailing newline for the last element
+        Ok(divs)
+    }
+
+    /// Render the contents of the head, where only complete documents are titled and described
+    fn head_html(&self, document: bool) -> Result<String, String> {
+        let mut head = String::new();
+        if let Some(title) = self.title.as_ref().filter(|_| document) {
+            head += &format!("<title>{}</title>\n", escape_html(title));
+        }
+        if let Some(description) = self.subtitle.as_ref().filter(|_| document) {
+            head += &format!(
+                "<meta name=\"description\" content=\"{}\">\n",
+                escape_html(description)
+            );
+        }
+        for tag in self.meta.as_deref().unwrap_or_default() {
+            let key = match (&tag.name, &tag.property) {
+                (Some(name), _) => format!("name=\"{}\"", escape_html(name)),
+                (None, Some(property)) => format!("property=\"{}\"", escape_html(property)),
+                (None, None) => {
+                    eprintln!("Skipping meta tag without a name or property");
+                    continue;
+                }
+            };
+            head += &format!("<meta {key} content=\"{}\">\n", escape_html(&tag.content));
+        }
+        if let Some(data) = &self.structured_data {
+            // Script contents are not HTML-escaped, so only a closing tag could end the block early
+            head += &format!(
+                "<script type=\"application/ld+json\">\n{}\n</script>\n",
+                data.replace("</", "<\\/")
+            );
+        }
+
+        if let Some(favicon) = &self.favicon {
+            // Ignore any query string when working out the type from the extension
+            let mime = mime_type(favicon.split(['?', '#']).next().unwrap_or_default());
+            let type_attribute = if mime.starts_with("image/") {
+                format!(" type=\"{mime}\"")
             } else {
-                divs += &html;
+                String::new()
+            };
+            head += &format!(
+                "<link rel=\"icon\" href=\"{}\"{type_attribute}>\n",
+                escape_html(favicon)
+            );
+        }
+        if let Some(theme) = &self.theme {
+            match THEMES.iter().find(|(name, _)| name == theme) {
+                Some((_, css)) => head += &format!("<style>\n{css}</style>\n"),
+                None => {
+                    let names: Vec<&str> = THEMES.iter().map(|(name, _)| *name).collect();
+                    return Err(format!(
+                        "Unknown theme '{theme}': expected one of {}",
+                        names.join(", ")
+                    ));
+                }
             }
         }
-        divs
+        for stylesheet in self.stylesheets.as_deref().unwrap_or_default() {
+            head += &format!(
+                "<link rel=\"stylesheet\" href=\"{}\">\n",
+                escape_html(stylesheet)
+            );
+        }
+
+        if let Some(raw) = &self.head_raw {
+            head += raw;
+            if !raw.ends_with('\n') {
+                head.push('\n');
+            }
+        }
+
+        Ok(head)
     }
+
+    /// Render the navigation menu, marking the link to the current page
+    fn nav_html(&self) -> String {
+        let items = self.nav.as_deref().unwrap_or_default();
+        if items.is_empty() {
+            return String::new();
+        }
+
+        let page = self.page.as_deref().map(page_path);
+        let mut nav = String::from("<nav>\n<ul>\n");
+        for item in items {
+            let current = if page == Some(page_path(&item.href)) {
+                " aria-current=\"page\""
+            } else {
+                ""
+            };
+            nav += &format!(
+                "<li><a href=\"{}\"{current}>{}</a></li>\n",
+                escape_html(&item.href),
+                escape_html(&item.label)
+            );
+        }
+        nav += "</ul>\n</nav>\n";
+        nav
+    }
+}
+
+/// Normalise a link so that `/page.html`, `./page.html` and `page.html` compare equal
+fn page_path(href: &str) -> &str {
+    href.trim_start_matches("./").trim_start_matches('/')
+}
+
+/// Marks where the contents of an element were taken out while minifying
+const PRESERVED_PLACEHOLDER: char = '\u{FFF9}';
+
+/// Collapse whitespace and drop comments, leaving the contents of pre, code, and textarea intact
+fn minify(html: &str) -> String {
+    static PRESERVED: OnceLock<[Regex; 3]> = OnceLock::new();
+    let preserved = PRESERVED.get_or_init(|| {
+        ["pre", "textarea", "code"]
+            .map(|tag| Regex::new(&format!(r"(?is)(<{tag}\b[^>]*>)(.*?)(</{tag}>)")).unwrap())
+    });
+
+    // Set the contents aside so the minifier cannot collapse them, starting with pre so code inside it goes too
+    let mut contents = Vec::new();
+    let mut html = html.to_string();
+    for element in preserved {
+        html = element
+            .replace_all(&html, |captures: &Captures| {
+                contents.push(captures[2].to_string());
+                format!(
+                    "{}{PRESERVED_PLACEHOLDER}{}{PRESERVED_PLACEHOLDER}{}",
+                    &captures[1],
+                    contents.len() - 1,
+                    &captures[3]
+                )
+            })
+            .into_owned();
+    }
+
+    let mut cfg = minify_html::Cfg::new();
+    // Closing tags are kept so exported pages can still be read back for feeds
+    cfg.keep_closing_tags = true;
+    cfg.keep_html_and_head_opening_tags = true;
+    let mut minified =
+        String::from_utf8_lossy(&minify_html::minify(html.as_bytes(), &cfg)).into_owned();
+
+    for (index, content) in contents.iter().enumerate() {
+        let placeholder = format!("{PRESERVED_PLACEHOLDER}{index}{PRESERVED_PLACEHOLDER}");
+        minified = minified.replacen(&placeholder, content, 1);
+    }
+    minified
 }
 
 /// Compile the input as outlined in the config to HTML
-fn compile_html(obj: &Object) -> String {
+fn compile_html(obj: &Object, conf: &Conf) -> Result<String, String> {
     let obj = obj.to_owned();
 
     let format = obj.format.unwrap_or_default();
+    let id_attribute = obj.id.map_or(String::new(), |id| format!(" id=\"{id}\""));
+
+    // Anything other than a built-in format is used as a tag name, so it must be a valid one
+    let is_builtin = matches!(format.as_str(), "br" | "hr" | "html" | "md" | "txt");
+    if !is_builtin && !is_tag_name(&format) {
+        return Err(format!(
+            "Unknown format '{format}': expected br, hr, html, md, txt, or a HTML tag name"
+        ));
+    }
+
+    // Objects with children become containers wrapping any content of their own
+    if let Some(children) = obj.children {
+        let mut inner = match obj.content_file {
+            None => obj
+                .content
+                .map(|content| format!("{}\n", format_text(&content))),
+            Some(file_path) => Some(format!("{}\n", format_text(&fs_to_str(&file_path)))),
+        }
+        .unwrap_or_default();
+        for child in &children {
+            inner += &compile_html(child, conf)?;
+        }
+        return Ok(format!("<{format}{id_attribute}>\n{inner}</{format}>\n"));
+    }
+
     let content = match obj.content_file {
         None => obj.content.unwrap_or_else(|| "PLACEHOLDER".to_string()),
         Some(file_path) => fs_to_str(&file_path),
     };
 
     let formatted_text = format_text(&content);
-    let id_attribute = obj.id.map_or(String::new(), |id| format!(" id=\"{id}\""));
 
     let html = match format.as_str() {
         "br" => "<br/>\n".to_string(),
         "hr" => "<hr/>\n".to_string(),
         "html" => format!("{}\n", formatted_text),
-        "md" => format!("{}\n", convert_markdown_to_html(&content)),
+        "md" => format!("{}\n", convert_markdown_to_html(&content, conf)),
+        "txt" => format!("<pre{id_attribute}>{}</pre>\n", escape_html(&content)),
         _ => format!("<{format}{id_attribute}>{formatted_text}</{format}>\n"),
     };
 
-    html
+    Ok(html)
+}
+
+/// Check whether a format can be used as the name of a HTML element
+fn is_tag_name(format: &str) -> bool {
+    format.starts_with(|c: char| c.is_ascii_alphabetic())
+        && format
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-')
+}
+
+/// Escape the characters that have special meaning in HTML
+pub fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Decode the character references that HTML uses to escape text, such as `&amp;` and `&#39;`
+pub fn unescape_html(text: &str) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        unescaped += &rest[..start];
+        rest = &rest[start..];
+
+        let decoded = rest.find(';').and_then(|end| {
+            let name = &rest[1..end];
+            let c = match name {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                _ => name
+                    .strip_prefix("#x")
+                    .or_else(|| name.strip_prefix("#X"))
+                    .map(|hex| u32::from_str_radix(hex, 16))
+                    .or_else(|| name.strip_prefix('#').map(str::parse::<u32>))
+                    .and_then(Result::ok)
+                    .and_then(char::from_u32),
+            };
+            c.map(|c| (c, end + 1))
+        });
+
+        // Anything that is not a reference is kept as it was written
+        match decoded {
+            Some((c, length)) => {
+                unescaped.push(c);
+                rest = &rest[length..];
+            }
+            None => {
+                unescaped.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    unescaped + rest
+}
+
+/// The Markdown extensions every document is parsed with, before any that are configurable
+fn markdown_options() -> Options {
+    Options::ENABLE_TABLES
+        | Options::ENABLE_FOOTNOTES
+        | Options::ENABLE_STRIKETHROUGH
+        | Options::ENABLE_TASKLISTS
+        | Options::ENABLE_HEADING_ATTRIBUTES
+        | Options::ENABLE_YAML_STYLE_METADATA_BLOCKS
+        | Options::ENABLE_GFM
+        | Options::ENABLE_DEFINITION_LIST
 }
 
 /// Convert a Markdown string to a HTML string
-fn convert_markdown_to_html(content: &str) -> String {
+fn convert_markdown_to_html(content: &str, conf: &Conf) -> String {
+    let toc = conf.toc.unwrap_or(false);
+
+    // Swap the marker for a HTML comment so it survives rendering untouched
+    let content = if toc {
+        content
+            .lines()
+            .map(|line| {
+                if line.trim() == TOC_MARKER {
+                    TOC_PLACEHOLDER
+                } else {
+                    line
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    } else {
+        content.to_string()
+    };
+
+    let (content, shortcodes) = expand_shortcodes(&content);
+
     let mut html_output = String::new();
-    let parser = Parser::new_ext(
-        content,
-        Options::ENABLE_TABLES
-            | Options::ENABLE_FOOTNOTES
-            | Options::ENABLE_STRIKETHROUGH
-            | Options::ENABLE_TASKLISTS
-            | Options::ENABLE_SMART_PUNCTUATION
-            | Options::ENABLE_HEADING_ATTRIBUTES
-            | Options::ENABLE_YAML_STYLE_METADATA_BLOCKS
-            | Options::ENABLE_MATH
-            | Options::ENABLE_GFM
-            | Options::ENABLE_DEFINITION_LIST,
-    );
-    html::push_html(&mut html_output, parser);
-    html_output
+    let mut options = markdown_options();
+    // Math is off unless asked for, as prices and other dollar signs would otherwise start it
+    // When on, it is marked up with math-inline and math-display classes for KaTeX or MathJax to render
+    if conf.math.unwrap_or(false) {
+        options |= Options::ENABLE_MATH;
+    }
+    // Curly quotes, dashes, and ellipses, which are never applied inside code
+    if conf.smart_punctuation.unwrap_or(false) {
+        options |= Options::ENABLE_SMART_PUNCTUATION;
+    }
+
+    let mut events = Parser::new_ext(&content, options).collect::<Vec<_>>();
+
+    if conf.highlight.unwrap_or(true) {
+        events = highlight_code_blocks(events, conf.code_line_numbers.unwrap_or(false));
+    }
+    if conf.lazy_images.unwrap_or(true) {
+        events = lazy_load_images(events);
+    }
+    let document_dirs = conf
+        .document_dirs
+        .clone()
+        .unwrap_or_else(|| vec![PathBuf::from("documents")]);
+    events = resolve_wikilinks(events, &document_dirs);
+    events = rewrite_document_links(events);
+    events = mark_external_links(events, conf);
+    if conf.emoji.unwrap_or(false) {
+        events = expand_emoji(events);
+    }
+    events = collect_footnotes(events);
+    let headings = assign_heading_ids(&mut events);
+    events = add_heading_anchors(events);
+
+    html::push_html(&mut html_output, events.into_iter());
+
+    if toc {
+        let toc_html = table_of_contents(&headings);
+        if html_output.contains(TOC_PLACEHOLDER) {
+            html_output = html_output.replacen(TOC_PLACEHOLDER, toc_html.trim_end(), 1);
+        } else {
+            html_output.insert_str(0, &toc_html);
+        }
+    }
+
+    if conf.sanitize_html.unwrap_or(false) {
+        html_output = sanitize(&html_output);
+    }
+    restore_shortcodes(&html_output, &shortcodes)
+}
+
+/// Strip scripts, event handlers, and other unsafe HTML, keeping what the Markdown renderer itself produces
+fn sanitize(html: &str) -> String {
+    ammonia::Builder::default()
+        .add_tags(["input", "section"])
+        .add_generic_attributes(["id", "class", "aria-hidden"])
+        .add_tag_attributes("a", ["target"])
+        .add_tag_attributes("img", ["title", "loading", "decoding"])
+        .add_tag_attributes("input", ["type", "checked", "disabled"])
+        .add_tag_attributes("pre", ["style"])
+        .add_tag_attributes("span", ["style"])
+        .add_tag_attributes("td", ["style"])
+        .add_tag_attributes("th", ["style"])
+        .clean(html)
+        .to_string()
+}
+
+/// Stands in for a rendered shortcode until the rest of the document has been rendered and sanitized
+const SHORTCODE_PLACEHOLDER: char = '\u{FFFC}';
+
+/// Replace `{{name key="value"}}` shortcodes outside of code with numbered placeholders,
+/// returning the HTML each one renders to
+///
+/// Shortcodes only put in the embeds they are built for, with their arguments escaped, so they are
+/// trusted and go in after sanitizing, which would otherwise strip their iframes and scripts
+fn expand_shortcodes(content: &str) -> (String, Vec<String>) {
+    static SHORTCODE: OnceLock<Regex> = OnceLock::new();
+    let shortcode = SHORTCODE
+        .get_or_init(|| Regex::new(r#"\{\{\s*([a-z_]+)((?:\s+[a-z_]+="[^"]*")*)\s*\}\}"#).unwrap());
+    if !shortcode.is_match(content) {
+        return (content.to_string(), Vec::new());
+    }
+
+    // Shortcodes written in code spans and code blocks are examples, so they are left as written
+    let code = Parser::new_ext(content, markdown_options())
+        .into_offset_iter()
+        .filter_map(|(event, range)| match event {
+            Event::Start(Tag::CodeBlock(_)) | Event::Code(_) => Some(range),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    let mut rendered = Vec::new();
+    let expanded = shortcode.replace_all(content, |captures: &regex::Captures| {
+        let position = captures.get(0).map_or(0, |found| found.start());
+        if code.iter().any(|range| range.contains(&position)) {
+            return captures[0].to_string();
+        }
+        rendered.push(render_shortcode(&captures[1], &captures[2]));
+        format!(
+            "{SHORTCODE_PLACEHOLDER}{}{SHORTCODE_PLACEHOLDER}",
+            rendered.len() - 1
+        )
+    });
+    (expanded.into_owned(), rendered)
+}
+
+/// Put rendered shortcodes in place of their placeholders, unwrapping any that stood alone as a paragraph
+fn restore_shortcodes(html: &str, shortcodes: &[String]) -> String {
+    if shortcodes.is_empty() {
+        return html.to_string();
+    }
+
+    let mut html = html.to_string();
+    for (index, shortcode) in shortcodes.iter().enumerate() {
+        let placeholder = format!("{SHORTCODE_PLACEHOLDER}{index}{SHORTCODE_PLACEHOLDER}");
+        html = html
+            .replacen(&format!("<p>{placeholder}</p>"), shortcode, 1)
+            .replacen(&placeholder, shortcode, 1);
+    }
+    html
+}
+
+/// Render one of the built-in shortcodes, or a comment explaining why it could not be rendered
+fn render_shortcode(name: &str, arguments: &str) -> String {
+    static ARGUMENT: OnceLock<Regex> = OnceLock::new();
+    let argument = ARGUMENT.get_or_init(|| Regex::new(r#"([a-z_]+)="([^"]*)""#).unwrap());
+    let arguments: HashMap<&str, String> = argument
+        .captures_iter(arguments)
+        .map(|captures| {
+            let (_, [key, value]) = captures.extract();
+            (key, escape_html(value))
+        })
+        .collect();
+    let get = |key: &str| arguments.get(key).map(String::as_str);
+
+    let html = match name {
+        "youtube" => get("id").map(|id| {
+            format!("<div class=\"video\"><iframe src=\"https://www.youtube-nocookie.com/embed/{id}\" title=\"YouTube video\" loading=\"lazy\" allowfullscreen></iframe></div>")
+        }),
+        "figure" => get("src").map(|src| {
+            let alt = get("alt").unwrap_or_default();
+            let caption = get("caption")
+                .map(|caption| format!("<figcaption>{caption}</figcaption>"))
+                .unwrap_or_default();
+            format!("<figure><img src=\"{src}\" alt=\"{alt}\">{caption}</figure>")
+        }),
+        "gist" => get("id")
+            .map(|id| format!("<script src=\"https://gist.github.com/{id}.js\"></script>")),
+        _ => return format!("<!-- Unknown shortcode: {name} -->"),
+    };
+
+    html.unwrap_or_else(|| {
+        let required = if name == "figure" { "src" } else { "id" };
+        format!("<!-- The {name} shortcode requires {required} -->")
+    })
+}
+
+/// Marks where in a document the table of contents should go
+const TOC_MARKER: &str = "[[TOC]]";
+const TOC_PLACEHOLDER: &str = "<!-- toc -->";
+
+/// Give every heading an id from its slugged text, returning the level, id, and text of each
+fn assign_heading_ids(events: &mut [Event]) -> Vec<(HeadingLevel, String, String)> {
+    let mut headings = Vec::new();
+    let mut used: HashMap<String, usize> = HashMap::new();
+
+    for i in 0..events.len() {
+        let Event::Start(Tag::Heading { level, id, .. }) = &events[i] else {
+            continue;
+        };
+        let level = *level;
+
+        let mut text = String::new();
+        for event in &events[i + 1..] {
+            match event {
+                Event::Text(t) | Event::Code(t) => text += t,
+                Event::End(TagEnd::Heading(_)) => break,
+                _ => {}
+            }
+        }
+
+        // Explicit ids are kept, and repeated headings are numbered to keep ids unique
+        let id = match id {
+            Some(id) => id.to_string(),
+            None => {
+                let slug = slugify(&text);
+                let count = used.entry(slug.clone()).or_default();
+                *count += 1;
+                if *count == 1 {
+                    slug
+                } else {
+                    format!("{slug}-{count}")
+                }
+            }
+        };
+
+        if let Event::Start(Tag::Heading { id: heading_id, .. }) = &mut events[i] {
+            *heading_id = Some(id.clone().into());
+        }
+        headings.push((level, id, text));
+    }
+    headings
+}
+
+/// Append a link to each heading pointing at its own id so sections can be linked to
+fn add_heading_anchors(events: Vec<Event>) -> Vec<Event> {
+    let mut output = Vec::with_capacity(events.len());
+    let mut current_id = None;
+    for event in events {
+        match &event {
+            Event::Start(Tag::Heading { id, .. }) => current_id = id.clone(),
+            Event::End(TagEnd::Heading(_)) => {
+                if let Some(id) = current_id.take() {
+                    output.push(Event::Html(
+                        format!(
+                            " <a class=\"anchor\" href=\"#{}\" aria-hidden=\"true\">#</a>",
+                            escape_html(&id)
+                        )
+                        .into(),
+                    ));
+                }
+            }
+            _ => {}
+        }
+        output.push(event);
+    }
+    output
+}
+
+/// Build a nested list linking to the second to fourth level headings
+fn table_of_contents(headings: &[(HeadingLevel, String, String)]) -> String {
+    let mut toc = String::from("<nav class=\"toc\">\n");
+    let mut depth = 0;
+
+    for (level, id, text) in headings {
+        let level = match level {
+            HeadingLevel::H2 => 1,
+            HeadingLevel::H3 => 2,
+            HeadingLevel::H4 => 3,
+            _ => continue,
+        };
+
+        // Descend at most one level at a time so skipped levels still nest validly
+        if level > depth {
+            toc += if depth == 0 { "<ul>\n" } else { "\n<ul>\n" };
+            depth += 1;
+        } else {
+            toc += "</li>\n";
+            while depth > level {
+                toc += "</ul>\n</li>\n";
+                depth -= 1;
+            }
+        }
+        toc += &format!(
+            "<li><a href=\"#{}\">{}</a>",
+            escape_html(id),
+            escape_html(text)
+        );
+    }
+
+    if depth > 0 {
+        toc += "</li>\n";
+        while depth > 1 {
+            toc += "</ul>\n</li>\n";
+            depth -= 1;
+        }
+        toc += "</ul>\n";
+    }
+    toc += "</nav>\n";
+    toc
+}
+
+/// Render images so the browser defers loading them, except for the first which is likely visible immediately
+fn lazy_load_images(events: Vec<Event>) -> Vec<Event> {
+    let mut output = Vec::with_capacity(events.len());
+    let mut first = true;
+    let mut events = events.into_iter();
+    while let Some(event) = events.next() {
+        let Event::Start(Tag::Image {
+            dest_url, title, ..
+        }) = &event
+        else {
+            output.push(event);
+            continue;
+        };
+
+        let mut alt = String::new();
+        for event in events.by_ref() {
+            match event {
+                Event::Text(text) | Event::Code(text) => alt += &text,
+                Event::End(TagEnd::Image) => break,
+                _ => {}
+            }
+        }
+
+        let title = if title.is_empty() {
+            String::new()
+        } else {
+            format!(" title=\"{}\"", escape_html(title))
+        };
+        let loading = if first { "" } else { " loading=\"lazy\"" };
+        first = false;
+        output.push(Event::Html(
+            format!(
+                "<img src=\"{}\" alt=\"{}\"{title}{loading} decoding=\"async\" />",
+                escape_html(dest_url),
+                escape_html(&alt)
+            )
+            .into(),
+        ));
+    }
+    output
+}
+
+/// Turn `[[Name]]` and `[[Name|Label]]` in prose into links to the exported document called Name
+fn resolve_wikilinks<'a>(events: Vec<Event<'a>>, document_dirs: &[PathBuf]) -> Vec<Event<'a>> {
+    let mut output = Vec::with_capacity(events.len());
+    let mut text = String::new();
+    let mut in_code_block = false;
+
+    for event in events {
+        // Text is buffered because the parser splits brackets into separate events
+        match event {
+            Event::Text(t) if !in_code_block => {
+                text += &t;
+                continue;
+            }
+            Event::Start(Tag::CodeBlock(_)) => in_code_block = true,
+            Event::End(TagEnd::CodeBlock) => in_code_block = false,
+            _ => {}
+        }
+        flush_wikilinks(&mut output, &mut text, document_dirs);
+        output.push(event);
+    }
+    flush_wikilinks(&mut output, &mut text, document_dirs);
+    output
+}
+
+/// Emit buffered text, replacing any wikilinks in it with links or broken link markers
+fn flush_wikilinks(output: &mut Vec<Event>, text: &mut String, document_dirs: &[PathBuf]) {
+    static WIKILINK: OnceLock<Regex> = OnceLock::new();
+    let wikilink =
+        WIKILINK.get_or_init(|| Regex::new(r"\[\[([^\[\]|]+)(?:\|([^\[\]]+))?\]\]").unwrap());
+    if text.is_empty() {
+        return;
+    }
+
+    let mut last = 0;
+    for captures in wikilink.captures_iter(text) {
+        let whole = captures.get(0).unwrap();
+        let name = captures[1].trim();
+        if name == TOC_MARKER.trim_matches(['[', ']']) {
+            continue;
+        }
+        let label = captures.get(2).map_or(name, |label| label.as_str().trim());
+
+        if whole.start() > last {
+            output.push(Event::Text(text[last..whole.start()].to_string().into()));
+        }
+        last = whole.end();
+
+        // Documents are found by their slug, or by the name exactly as written
+        let target = [slugify(name), name.to_string()]
+            .into_iter()
+            .find(|document| {
+                document_dirs
+                    .iter()
+                    .any(|dir| dir.join(format!("{document}.md")).is_file())
+            });
+        let html = match target {
+            Some(document) => format!(
+                "<a href=\"{}.html\">{}</a>",
+                escape_html(&document),
+                escape_html(label)
+            ),
+            None => format!(
+                "<span class=\"broken-link\" title=\"No document named {}\">{}</span>",
+                escape_html(name),
+                escape_html(label)
+            ),
+        };
+        output.push(Event::Html(html.into()));
+    }
+
+    if last < text.len() {
+        output.push(Event::Text(text[last..].to_string().into()));
+    }
+    text.clear();
+}
+
+/// Point relative links to Markdown documents at the HTML they are exported to
+fn rewrite_document_links(events: Vec<Event>) -> Vec<Event> {
+    events
+        .into_iter()
+        .map(|event| match event {
+            Event::Start(Tag::Link {
+                link_type,
+                dest_url,
+                title,
+                id,
+            }) => Event::Start(Tag::Link {
+                link_type,
+                dest_url: exported_link(&dest_url).map_or(dest_url, Into::into),
+                title,
+                id,
+            }),
+            event => event,
+        })
+        .collect()
+}
+
+/// Rewrite a relative link ending in .md to end in .html, keeping any query or fragment
+fn exported_link(url: &str) -> Option<String> {
+    let is_absolute = url.starts_with('/')
+        || url.starts_with('#')
+        || url
+            .split_once(':')
+            .is_some_and(|(scheme, _)| !scheme.contains(['/', '?', '#']));
+    if is_absolute {
+        return None;
+    }
+
+    let end = url.find(['?', '#']).unwrap_or(url.len());
+    let (path, suffix) = url.split_at(end);
+    let stem = path.strip_suffix(".md")?;
+    Some(format!("{stem}.html{suffix}"))
+}
+
+/// Add rel="noopener noreferrer" to links leaving the site, and optionally open them in a new tab
+fn mark_external_links<'a>(events: Vec<Event<'a>>, conf: &Conf) -> Vec<Event<'a>> {
+    let site_host = conf.base_url.as_deref().and_then(url_host);
+    let new_tab = conf.external_links_new_tab.unwrap_or(false);
+
+    events
+        .into_iter()
+        .map(|event| match &event {
+            Event::Start(Tag::Link {
+                dest_url, title, ..
+            }) => {
+                let Some(host) = url_host(dest_url) else {
+                    return event;
+                };
+                if site_host.is_some_and(|site_host| site_host.eq_ignore_ascii_case(host)) {
+                    return event;
+                }
+
+                let title = if title.is_empty() {
+                    String::new()
+                } else {
+                    format!(" title=\"{}\"", escape_html(title))
+                };
+                let target = if new_tab { " target=\"_blank\"" } else { "" };
+                Event::Html(
+                    format!(
+                        "<a href=\"{}\"{title} rel=\"noopener noreferrer\"{target}>",
+                        escape_html(dest_url)
+                    )
+                    .into(),
+                )
+            }
+            _ => event,
+        })
+        .collect()
+}
+
+/// Find the host of an absolute or protocol-relative URL
+fn url_host(url: &str) -> Option<&str> {
+    let rest = url
+        .strip_prefix("https://")
+        .or_else(|| url.strip_prefix("http://"))
+        .or_else(|| url.strip_prefix("//"))?;
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or_default();
+    let host = authority.rsplit('@').next().unwrap_or_default();
+    let host = host.split(':').next().unwrap_or_default();
+    (!host.is_empty()).then_some(host)
+}
+
+/// Replace `:shortcode:` tokens in prose with their emoji, leaving code untouched
+fn expand_emoji(events: Vec<Event>) -> Vec<Event> {
+    let mut in_code_block = false;
+    events
+        .into_iter()
+        .map(|event| match event {
+            Event::Start(Tag::CodeBlock(_)) => {
+                in_code_block = true;
+                event
+            }
+            Event::End(TagEnd::CodeBlock) => {
+                in_code_block = false;
+                event
+            }
+            Event::Text(text) if !in_code_block && text.contains(':') => {
+                Event::Text(replace_shortcodes(&text).into())
+            }
+            event => event,
+        })
+        .collect()
+}
+
+/// Replace each known `:shortcode:` in a string, keeping unknown ones as written
+fn replace_shortcodes(text: &str) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find(':') {
+        output += &rest[..start];
+        let after = &rest[start + 1..];
+        let emoji = after
+            .find(':')
+            .map(|end| &after[..end])
+            .filter(|name| {
+                !name.is_empty()
+                    && name
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '+' | '-'))
+            })
+            .and_then(|name| emojis::get_by_shortcode(name).map(|emoji| (name, emoji)));
+
+        match emoji {
+            Some((name, emoji)) => {
+                output += emoji.as_str();
+                rest = &after[name.len() + 1..];
+            }
+            None => {
+                output.push(':');
+                rest = after;
+            }
+        }
+    }
+    output += rest;
+    output
+}
+
+/// Move footnote definitions into a numbered section at the end, linking references and definitions both ways
+fn collect_footnotes(events: Vec<Event>) -> Vec<Event> {
+    let mut output = Vec::with_capacity(events.len());
+    let mut order: Vec<String> = Vec::new();
+    let mut references: HashMap<String, usize> = HashMap::new();
+    let mut definitions: HashMap<String, Vec<Event>> = HashMap::new();
+
+    let mut events = events.into_iter();
+    while let Some(event) = events.next() {
+        match event {
+            Event::FootnoteReference(label) => {
+                let label = label.to_string();
+                if !order.contains(&label) {
+                    order.push(label.clone());
+                }
+                let number = order.iter().position(|l| *l == label).unwrap_or_default() + 1;
+                let count = references.entry(label.clone()).or_default();
+                *count += 1;
+
+                let id = footnote_id(&label);
+                let reference_id = if *count == 1 {
+                    format!("fnref-{id}")
+                } else {
+                    format!("fnref-{id}-{count}")
+                };
+                output.push(Event::Html(
+                    format!("<sup class=\"footnote-reference\" id=\"{reference_id}\"><a href=\"#fn-{id}\">{number}</a></sup>").into(),
+                ));
+            }
+            Event::Start(Tag::FootnoteDefinition(label)) => {
+                let mut definition = Vec::new();
+                for event in events.by_ref() {
+                    if let Event::End(TagEnd::FootnoteDefinition) = event {
+                        break;
+                    }
+                    definition.push(event);
+                }
+                definitions.insert(label.to_string(), definition);
+            }
+            event => output.push(event),
+        }
+    }
+
+    // Definitions that are never referenced still appear, after the referenced ones
+    let mut unreferenced = definitions
+        .keys()
+        .filter(|label| !order.contains(label))
+        .cloned()
+        .collect::<Vec<_>>();
+    unreferenced.sort();
+    order.extend(unreferenced);
+    order.retain(|label| definitions.contains_key(label));
+    if order.is_empty() {
+        return output;
+    }
+
+    let mut section = String::from("<section class=\"footnotes\">\n<ol>\n");
+    for label in &order {
+        let id = footnote_id(label);
+        section += &format!("<li id=\"fn-{id}\">\n");
+        html::push_html(
+            &mut section,
+            definitions.remove(label).unwrap_or_default().into_iter(),
+        );
+        for count in 1..=references.get(label).copied().unwrap_or_default() {
+            let reference_id = if count == 1 {
+                format!("fnref-{id}")
+            } else {
+                format!("fnref-{id}-{count}")
+            };
+            section += &format!("<a href=\"#{reference_id}\" class=\"footnote-backref\">↩</a>\n");
+        }
+        section += "</li>\n";
+    }
+    section += "</ol>\n</section>\n";
+    output.push(Event::Html(section.into()));
+    output
+}
+
+/// Turn a footnote label into something usable in an id attribute
+fn footnote_id(label: &str) -> String {
+    match slugify(label) {
+        slug if slug.is_empty() => escape_html(label),
+        slug => slug,
+    }
+}
+
+/// Replace fenced code blocks in a known language with syntax highlighted HTML
+fn highlight_code_blocks(events: Vec<Event>, line_numbers: bool) -> Vec<Event> {
+    static SYNTAXES: OnceLock<SyntaxSet> = OnceLock::new();
+    static THEMES: OnceLock<ThemeSet> = OnceLock::new();
+
+    let mut highlighted = Vec::with_capacity(events.len());
+    let mut events = events.into_iter();
+    while let Some(event) = events.next() {
+        let Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(language))) = &event else {
+            highlighted.push(event);
+            continue;
+        };
+
+        // The language may be followed by attributes, as in rust{start=10}
+        let (token, attributes) = language.split_once('{').unwrap_or((language, ""));
+        let start = attributes
+            .trim_end_matches('}')
+            .split([',', ' '])
+            .find_map(|attribute| attribute.trim().strip_prefix("start="))
+            .and_then(|start| start.parse::<usize>().ok())
+            .unwrap_or(1);
+
+        // Blocks without a recognised language are left for the default renderer
+        let syntaxes = SYNTAXES.get_or_init(SyntaxSet::load_defaults_newlines);
+        let Some(syntax) = token
+            .split_whitespace()
+            .next()
+            .and_then(|token| syntaxes.find_syntax_by_token(token))
+        else {
+            highlighted.push(event);
+            continue;
+        };
+
+        let mut code = String::new();
+        for event in events.by_ref() {
+            match event {
+                Event::Text(text) => code += &text,
+                Event::End(TagEnd::CodeBlock) => break,
+                _ => {}
+            }
+        }
+
+        let theme = &THEMES.get_or_init(ThemeSet::load_defaults).themes["InspiredGitHub"];
+        let result = if line_numbers {
+            numbered_html_for_string(&code, syntaxes, syntax, theme, start)
+        } else {
+            highlighted_html_for_string(&code, syntaxes, syntax, theme)
+        };
+        match result {
+            Ok(html) => highlighted.push(Event::Html(html.into())),
+            Err(e) => {
+                eprintln!("Failed to highlight {} code block: {e}", syntax.name);
+                highlighted.push(Event::Html(
+                    format!("<pre><code>{}</code></pre>\n", escape_html(&code)).into(),
+                ));
+            }
+        }
+    }
+    highlighted
+}
+
+/// Highlight code with each line wrapped in a span and preceded by a number that cannot be selected
+fn numbered_html_for_string(
+    code: &str,
+    syntaxes: &SyntaxSet,
+    syntax: &SyntaxReference,
+    theme: &Theme,
+    start: usize,
+) -> Result<String, syntect::Error> {
+    let mut highlighter = HighlightLines::new(syntax, theme);
+    let (mut html, _) = start_highlighted_html_snippet(theme);
+    for (i, line) in LinesWithEndings::from(code).enumerate() {
+        let regions = highlighter.highlight_line(line, syntaxes)?;
+        let line_html = styled_line_to_highlighted_html(&regions, IncludeBackground::No)?;
+        html += &format!(
+            "<span class=\"line\"><span class=\"line-number\" style=\"user-select: none;\" aria-hidden=\"true\">{} </span>{line_html}</span>",
+            start + i
+        );
+    }
+    html += "</pre>\n";
+    Ok(html)
 }
 
 /// Format text by adding indentation
@@ -76,3 +1099,762 @@ fn format_text(content: &str) -> String {
         }) // Avoid leading newline for the first element
         .collect::<String>()
 }
+
+/// Convert text into a lowercase, hyphen-separated slug
+pub fn slugify(text: &str) -> String {
+    let mut slug = String::new();
+    for c in text.chars().flat_map(char::to_lowercase) {
+        if c.is_alphanumeric() {
+            slug.push(c);
+        } else if (c.is_whitespace() || c == '-') && !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    slug.trim_end_matches('-').to_string()
+}
+
+/// Marks the end of a document's excerpt
+pub const EXCERPT_MARKER: &str = "<!-- more -->";
+
+/// Summarise a Markdown string as plain text, using everything before an excerpt marker or else the first paragraph
+pub fn excerpt(content: &str, length: usize) -> String {
+    let text = match content.find(EXCERPT_MARKER) {
+        Some(end) => plain_text(&content[..end]),
+        None => first_paragraph(content),
+    };
+    let words = text.split_whitespace().collect::<Vec<_>>();
+
+    // Stop at the last whole word that fits, marking that the text was cut short
+    let mut summary = String::new();
+    for word in &words {
+        let separator = usize::from(!summary.is_empty());
+        if summary.chars().count() + separator + word.chars().count() > length {
+            return format!("{summary}…");
+        }
+        if separator == 1 {
+            summary.push(' ');
+        }
+        summary += word;
+    }
+    summary
+}
+
+/// Find the text of the first paragraph in a Markdown string, keeping its punctuation as written
+fn first_paragraph(content: &str) -> String {
+    let mut text = String::new();
+    let mut in_paragraph = false;
+    for event in Parser::new_ext(content, markdown_options()) {
+        match event {
+            Event::Start(Tag::Paragraph) => in_paragraph = true,
+            Event::End(TagEnd::Paragraph) if in_paragraph => break,
+            Event::Text(t) | Event::Code(t) if in_paragraph => text += &t,
+            Event::SoftBreak | Event::HardBreak if in_paragraph => text.push(' '),
+            _ => {}
+        }
+    }
+    text
+}
+
+/// Strip the Markdown syntax from a string, leaving only its text with its punctuation as written
+pub fn plain_text(content: &str) -> String {
+    let mut text = String::new();
+    for event in Parser::new_ext(content, markdown_options()) {
+        match event {
+            Event::Text(t) | Event::Code(t) => text += &t,
+            // Inline markup sits inside words and sentences, so only the end of a block separates text
+            Event::End(
+                TagEnd::Emphasis
+                | TagEnd::Strong
+                | TagEnd::Strikethrough
+                | TagEnd::Link
+                | TagEnd::Image,
+            ) => {}
+            Event::SoftBreak | Event::HardBreak | Event::End(_) => text.push('\n'),
+            _ => {}
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain_text_and_excerpt_keep_straight_quotes() {
+        assert_eq!(plain_text("# Rock & Roll's").trim(), "Rock & Roll's");
+        assert_eq!(
+            excerpt("It's \"quoted\" -- here...", 200),
+            "It's \"quoted\" -- here..."
+        );
+    }
+
+    #[test]
+    fn plain_text_only_separates_blocks() {
+        let text = plain_text("# Title\n\nSee [this](x). Some *emph*, here. **un**believable");
+        assert_eq!(text.split_whitespace().count(), 7);
+        assert!(text.contains("this."));
+        assert!(text.contains("unbelievable"));
+    }
+
+    #[test]
+    fn unescape_html_reverses_escape_html() {
+        let text = "Rock & Roll's <\"greatest\"> hits";
+        assert_eq!(unescape_html(&escape_html(text)), text);
+    }
+
+    #[test]
+    fn unescape_html_decodes_numeric_references_and_keeps_stray_ampersands() {
+        assert_eq!(
+            unescape_html("&#8217; &#x2019; & &bogus; &amp"),
+            "’ ’ & &bogus; &amp"
+        );
+    }
+
+    #[test]
+    fn unknown_formats_are_errors_naming_the_format() {
+        let conf: Conf = toml::from_str(
+            "[[main.block]]\nformat = \"div\"\nchildren = [{ format = \"m d\", content = \"x\" }]\n",
+        )
+        .unwrap();
+        let err = conf.to_html().unwrap_err();
+        assert!(err.contains("'m d'"), "{err}");
+    }
+
+    #[test]
+    fn unknown_themes_are_errors_listing_the_themes() {
+        let conf = Conf {
+            theme: Some("neon".to_string()),
+            ..Default::default()
+        };
+        let err = conf.to_html().unwrap_err();
+        assert!(err.contains("'neon'"), "{err}");
+        assert!(err.contains("minimal, dark, serif"), "{err}");
+    }
+
+    #[test]
+    fn shortcodes_survive_sanitizing() {
+        let conf = Conf {
+            sanitize_html: Some(true),
+            ..Default::default()
+        };
+        let html = convert_markdown_to_html(
+            "{{youtube id=\"abc\"}}\n\nSee {{gist id=\"me/123\"}}\n\n<script>alert(1)</script>\n",
+            &conf,
+        );
+        assert!(html.contains(
+            "<div class=\"video\"><iframe src=\"https://www.youtube-nocookie.com/embed/abc\""
+        ));
+        assert!(
+            html.contains("<p>See <script src=\"https://gist.github.com/me/123.js\"></script></p>")
+        );
+        assert!(!html.contains("<p><div"));
+        assert!(!html.contains("alert"));
+        assert!(!html.contains(SHORTCODE_PLACEHOLDER));
+    }
+
+    #[test]
+    fn slugify_keeps_letters_and_numbers_joined_by_hyphens() {
+        assert_eq!(slugify("Hello, World!"), "hello-world");
+        assert_eq!(slugify("  Rust -- 2024 edition  "), "rust-2024-edition");
+        assert_eq!(slugify("Café Crème"), "café-crème");
+        assert_eq!(slugify("?!"), "");
+    }
+
+    #[test]
+    fn excerpt_uses_the_first_paragraph_or_the_marker() {
+        assert_eq!(
+            excerpt("# Title\n\nFirst *paragraph*.\n\nSecond.\n", 200),
+            "First paragraph."
+        );
+        assert_eq!(
+            excerpt("One.\n\nTwo.\n\n<!-- more -->\n\nThree.\n", 200),
+            "One. Two."
+        );
+    }
+
+    #[test]
+    fn excerpt_cuts_at_a_whole_word() {
+        assert_eq!(excerpt("The quick brown fox", 12), "The quick…");
+        assert_eq!(excerpt("The quick brown fox", 19), "The quick brown fox");
+    }
+
+    #[test]
+    fn exported_link_rewrites_relative_markdown_links() {
+        assert_eq!(exported_link("post.md").as_deref(), Some("post.html"));
+        assert_eq!(
+            exported_link("../notes/post.md?v=1#intro").as_deref(),
+            Some("../notes/post.html?v=1#intro")
+        );
+        for unchanged in [
+            "https://example.com/post.md",
+            "/post.md",
+            "#post.md",
+            "mailto:me@example.com",
+            "image.png",
+        ] {
+            assert_eq!(exported_link(unchanged), None, "{unchanged}");
+        }
+    }
+
+    fn headings(content: &str) -> Vec<(HeadingLevel, String, String)> {
+        let mut events = Parser::new_ext(content, markdown_options()).collect::<Vec<_>>();
+        assign_heading_ids(&mut events)
+    }
+
+    #[test]
+    fn assign_heading_ids_numbers_repeats_and_keeps_explicit_ids() {
+        let ids = headings("# Notes\n## Notes\n## `Code` notes\n## Notes {#custom}\n### Notes\n")
+            .into_iter()
+            .map(|(_, id, _)| id)
+            .collect::<Vec<_>>();
+        assert_eq!(ids, ["notes", "notes-2", "code-notes", "custom", "notes-3"]);
+    }
+
+    #[test]
+    fn table_of_contents_nests_second_to_fourth_level_headings() {
+        let toc = table_of_contents(&headings(
+            "# Title\n## One\n### One & a half\n## Two\n#### Deep\n##### Too deep\n",
+        ));
+        assert_eq!(
+            toc,
+            "<nav class=\"toc\">\n<ul>\n\
+             <li><a href=\"#one\">One</a>\n<ul>\n\
+             <li><a href=\"#one-a-half\">One &amp; a half</a></li>\n</ul>\n</li>\n\
+             <li><a href=\"#two\">Two</a>\n<ul>\n\
+             <li><a href=\"#deep\">Deep</a></li>\n</ul>\n</li>\n\
+             </ul>\n</nav>\n"
+        );
+    }
+
+    #[test]
+    fn collect_footnotes_numbers_by_first_reference() {
+        let content = "A[^b] B[^a] again[^b]\n\n[^a]: Note a.\n[^b]: Note b.\n[^c]: Unused.\n";
+        let events = Parser::new_ext(content, markdown_options()).collect::<Vec<_>>();
+        let mut html_output = String::new();
+        html::push_html(&mut html_output, collect_footnotes(events).into_iter());
+
+        assert!(html_output.contains(
+            "<sup class=\"footnote-reference\" id=\"fnref-b\"><a href=\"#fn-b\">1</a></sup>"
+        ));
+        assert!(html_output.contains(
+            "<sup class=\"footnote-reference\" id=\"fnref-a\"><a href=\"#fn-a\">2</a></sup>"
+        ));
+        assert!(html_output.contains("id=\"fnref-b-2\""));
+
+        let section = &html_output[html_output.find("<section class=\"footnotes\">").unwrap()..];
+        let order =
+            ["fn-b", "fn-a", "fn-c"].map(|id| section.find(&format!("<li id=\"{id}\">")).unwrap());
+        assert!(order.is_sorted());
+        assert!(section.contains("<a href=\"#fnref-b-2\" class=\"footnote-backref\">"));
+    }
+
+    fn page(conf: &str) -> String {
+        toml::from_str::<Conf>(conf).unwrap().to_html().unwrap()
+    }
+
+    #[test]
+    fn nav_lists_links_in_order_and_marks_the_current_page() {
+        let mut conf: Conf = toml::from_str(
+            r#"
+nav = [
+    { label = "Home", href = "/" },
+    { label = "About", href = "/about.html" },
+    { label = "Posts & notes", href = "posts.html" },
+]
+"#,
+        )
+        .unwrap();
+        conf.page = Some("about.html".to_string());
+        let html = conf.to_html().unwrap();
+        assert!(html.starts_with(
+            "<nav>\n<ul>\n\
+             <li><a href=\"/\">Home</a></li>\n\
+             <li><a href=\"/about.html\" aria-current=\"page\">About</a></li>\n\
+             <li><a href=\"posts.html\">Posts &amp; notes</a></li>\n\
+             </ul>\n</nav>"
+        ));
+        assert!(!page("nav = []\n").contains("<nav>"));
+    }
+
+    #[test]
+    fn meta_tags_render_in_order_with_escaped_content() {
+        let html = page(
+            r##"
+meta = [
+    { name = "viewport", content = "width=device-width, initial-scale=1" },
+    { name = "theme-color", content = "#123456" },
+    { property = "og:site_name", content = "Rock & Roll" },
+]
+"##,
+        );
+        assert!(html.contains(
+            "<head>\n\
+             <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n\
+             <meta name=\"theme-color\" content=\"#123456\">\n\
+             <meta property=\"og:site_name\" content=\"Rock &amp; Roll\">\n\
+             </head>"
+        ));
+    }
+
+    #[test]
+    fn footer_renders_markdown_with_the_current_year() {
+        let html = page(
+            "footer = \"© {{year}} *Me*\"\n[[main.block]]\nformat = \"p\"\ncontent = \"Body\"\n",
+        );
+        let year = Local::now().year();
+        assert!(html.ends_with(&format!(
+            "<p>Body</p>\n<footer>\n<p>© {year} <em>Me</em></p>\n</footer>"
+        )));
+        assert!(!page("").contains("<footer>"));
+    }
+
+    #[test]
+    fn stylesheets_go_in_the_head_and_scripts_end_the_body() {
+        let html = page(
+            r#"
+stylesheets = ["style.css"]
+scripts = [{ src = "app.js", defer = true }, { src = "https://example.com/a.js?x=1&y=2" }]
+
+[[main.block]]
+format = "p"
+content = "Body"
+"#,
+        );
+        assert!(html.starts_with(
+            "<head>\n<link rel=\"stylesheet\" href=\"style.css\">\n</head>\n<body>\n<p>Body</p>\n"
+        ));
+        assert!(html.ends_with(
+            "<script src=\"app.js\" defer></script>\n\
+             <script src=\"https://example.com/a.js?x=1&amp;y=2\"></script>\n</body>"
+        ));
+    }
+
+    #[test]
+    fn head_raw_is_inserted_unmodified_at_the_end_of_the_head() {
+        let snippet = "<link rel=\"preconnect\" href=\"https://fonts.example.com\">\n<script>track('a & b')</script>";
+        let html = page(&format!(
+            "stylesheets = [\"style.css\"]\nhead_raw = '''\n{snippet}'''\n"
+        ));
+        assert!(html.contains(&format!("style.css\">\n{snippet}\n</head>")));
+    }
+
+    #[test]
+    fn favicon_is_linked_with_a_type_from_its_extension() {
+        assert!(page("favicon = \"/icon.svg?v=2\"\n")
+            .contains("<link rel=\"icon\" href=\"/icon.svg?v=2\" type=\"image/svg+xml\">"));
+        assert!(page("favicon = \"favicon.ico\"\n")
+            .contains("<link rel=\"icon\" href=\"favicon.ico\" type=\"image/x-icon\">"));
+        assert!(page("favicon = \"/icon\"\n").contains("<link rel=\"icon\" href=\"/icon\">"));
+    }
+
+    #[test]
+    fn themes_are_inlined_before_linked_stylesheets() {
+        let html = page("theme = \"dark\"\nstylesheets = [\"site.css\"]\n");
+        let theme = html.find("<style>\n").unwrap();
+        let stylesheet = html
+            .find("<link rel=\"stylesheet\" href=\"site.css\">")
+            .unwrap();
+        assert!(theme < stylesheet);
+        assert!(html.contains(THEMES[1].1));
+    }
+
+    #[test]
+    fn shortcodes_in_prose_expand() {
+        let html =
+            convert_markdown_to_html("Watch {{youtube id=\"abc\"}} now.\n", &Conf::default());
+        assert!(html.contains("<p>Watch <div class=\"video\"><iframe src=\"https://www.youtube-nocookie.com/embed/abc\""));
+    }
+
+    #[test]
+    fn shortcodes_in_code_stay_literal() {
+        let content = "Use `{{youtube id=\"abc\"}}` inline.\n\n    {{gist id=\"me/1\"}}\n\n```\n{{figure src=\"a.png\"}}\n```\n";
+        let html = convert_markdown_to_html(
+            content,
+            &Conf {
+                highlight: Some(false),
+                ..Default::default()
+            },
+        );
+        assert!(
+            html.contains(r#"<code>{{youtube id="abc"}}</code>"#),
+            "{html}"
+        );
+        assert!(html.contains("<pre><code>{{gist id=\"me/1\"}}\n</code></pre>"));
+        assert!(html.contains("<pre><code>{{figure src=\"a.png\"}}\n</code></pre>"));
+        assert!(
+            !html.contains("<iframe") && !html.contains("<script") && !html.contains("<img"),
+            "{html}"
+        );
+    }
+
+    #[test]
+    fn unknown_shortcodes_render_as_a_comment() {
+        let html =
+            convert_markdown_to_html("{{vimeo id=\"abc\"}}\n\n{{youtube}}\n", &Conf::default());
+        assert!(html.contains("<!-- Unknown shortcode: vimeo -->"));
+        assert!(html.contains("<!-- The youtube shortcode requires id -->"));
+    }
+
+    #[test]
+    fn minify_shrinks_pages_but_keeps_code_and_textareas() {
+        let conf = |minify: bool| {
+            format!(
+                r#"
+minify = {minify}
+highlight = false
+
+[[main.block]]
+format = "md"
+content = """
+# Title
+
+<!-- a comment -->
+
+Some    prose with `inline   code`.
+
+```
+fn main() {{
+    println!("hi");
+}}
+```
+"""
+
+[[main.block]]
+format = "html"
+content = """
+<div>
+    <textarea>  line one
+  line two</textarea>
+</div>
+"""
+"#
+            )
+        };
+        let plain = page(&conf(false));
+        let minified = page(&conf(true));
+
+        assert!(minified.len() < plain.len(), "{minified}");
+        assert!(!minified.contains("a comment"));
+        assert!(!minified.contains("Some    prose"));
+        assert!(minified.contains("<code>inline   code</code>"));
+        let code = "<pre><code>fn main() {\n    println!(\"hi\");\n}\n</code></pre>";
+        assert!(plain.contains(code));
+        assert!(minified.contains(code));
+        assert!(minified.contains("<textarea>  line one\n  line two</textarea>"));
+        assert!(!minified.contains(PRESERVED_PLACEHOLDER));
+    }
+
+    #[test]
+    fn math_is_only_rendered_when_enabled() {
+        let conf = |math: &str| {
+            format!(
+                r#"
+{math}
+
+[[main.block]]
+format = "md"
+content = """
+Inline $x^2$ and a price of \\$5.
+
+$$\\sum_i i$$
+"""
+"#
+            )
+        };
+
+        let html = page(&conf("math = true"));
+        assert!(html.contains(r#"<span class="math math-inline">x^2</span>"#));
+        assert!(html.contains(r#"<span class="math math-display">\sum_i i</span>"#));
+        assert!(html.contains("a price of $5."));
+
+        for off in ["", "math = false"] {
+            let html = page(&conf(off));
+            assert!(!html.contains("class=\"math"), "{off}");
+            assert!(html.contains("Inline $x^2$"), "{off}");
+            assert!(html.contains("$$\\sum_i i$$"), "{off}");
+        }
+    }
+
+    #[test]
+    fn pages_leave_the_title_and_language_to_documents() {
+        let conf = r#"
+title = "Site"
+subtitle = "About the site"
+lang = "en"
+
+[[main.block]]
+format = "p"
+content = "Body"
+"#;
+        assert_eq!(page(conf), "<p>Body</p>");
+
+        let document = toml::from_str::<Conf>(conf).unwrap().to_document().unwrap();
+        assert_eq!(
+            document,
+            "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n\
+             <title>Site</title>\n<meta name=\"description\" content=\"About the site\">\n\
+             </head>\n<body>\n<p>Body</p>\n</body>\n</html>"
+        );
+    }
+
+    #[test]
+    fn nested_blocks_render_inside_their_containers_in_order() {
+        let html = page(
+            r#"
+[[main.block]]
+format = "section"
+id = "cards"
+children = [
+    { format = "header", children = [{ format = "h2", content = "Cards" }] },
+    { format = "div", content = "<p>Intro</p>", children = [{ format = "p", content = "One" }, { format = "p", content = "Two" }] },
+]
+
+[[main.block]]
+format = "p"
+content = "After"
+"#,
+        );
+        assert_eq!(
+            html,
+            "<section id=\"cards\">\n\
+             <header>\n<h2>Cards</h2>\n</header>\n\
+             <div>\n<p>Intro</p>\n<p>One</p>\n<p>Two</p>\n</div>\n\
+             </section>\n\
+             <p>After</p>"
+        );
+    }
+
+    #[test]
+    fn fenced_code_is_highlighted_by_its_language() {
+        let content =
+            "```rust\nfn main() {}\n```\n\n```\nplain text\n```\n\n```nosuchlang\nplain too\n```\n";
+        let html = convert_markdown_to_html(content, &Conf::default());
+        assert!(html.contains("<pre style=\""), "{html}");
+        assert!(html.contains("<span style=\""), "{html}");
+        assert!(!html.contains("<code class=\"language-rust\">"), "{html}");
+        assert!(
+            html.contains("<pre><code>plain text\n</code></pre>"),
+            "{html}"
+        );
+        assert!(
+            html.contains("<pre><code class=\"language-nosuchlang\">plain too\n</code></pre>"),
+            "{html}"
+        );
+
+        let plain = convert_markdown_to_html(
+            content,
+            &Conf {
+                highlight: Some(false),
+                ..Default::default()
+            },
+        );
+        assert!(!plain.contains("<span style="), "{plain}");
+        assert!(plain.contains("<pre><code class=\"language-rust\">fn main() {}\n</code></pre>"));
+    }
+
+    #[test]
+    fn smart_punctuation_is_opt_in_and_skips_code() {
+        let content =
+            "\"Quoted\" -- and --- wait... `\"code\" -- ...`\n\n```\n\"block\" ---\n```\n";
+        let conf = |smart_punctuation| Conf {
+            smart_punctuation,
+            highlight: Some(false),
+            ..Default::default()
+        };
+
+        let html = convert_markdown_to_html(content, &conf(Some(true)));
+        assert!(html.contains("<p>“Quoted” – and — wait…"), "{html}");
+        assert!(html.contains("<code>\"code\" -- ...</code>"), "{html}");
+        assert!(
+            html.contains("<pre><code>\"block\" ---\n</code></pre>"),
+            "{html}"
+        );
+
+        for smart_punctuation in [None, Some(false)] {
+            let html = convert_markdown_to_html(content, &conf(smart_punctuation));
+            assert!(html.contains("<p>\"Quoted\" -- and --- wait..."), "{html}");
+        }
+    }
+
+    #[test]
+    fn images_after_the_first_load_lazily() {
+        let content = "![Hero](hero.png)\n\n![A \"cat\"](cat.png \"Cat\")\n";
+        let html = convert_markdown_to_html(content, &Conf::default());
+        assert!(
+            html.contains("<img src=\"hero.png\" alt=\"Hero\" decoding=\"async\" />"),
+            "{html}"
+        );
+        assert!(
+            html.contains("<img src=\"cat.png\" alt=\"A &quot;cat&quot;\" title=\"Cat\" loading=\"lazy\" decoding=\"async\" />"),
+            "{html}"
+        );
+
+        let eager = convert_markdown_to_html(
+            content,
+            &Conf {
+                lazy_images: Some(false),
+                ..Default::default()
+            },
+        );
+        assert!(
+            !eager.contains("loading=") && !eager.contains("decoding="),
+            "{eager}"
+        );
+        assert!(eager.contains("<img src=\"cat.png\""), "{eager}");
+    }
+
+    #[test]
+    fn only_external_links_get_rel_attributes() {
+        let content = "[out](https://other.example.org/a \"Other\") [home](https://Example.com/about) [relative](about.html) [anchor](#top)\n";
+        let conf = |external_links_new_tab| Conf {
+            base_url: Some("https://example.com/".to_string()),
+            external_links_new_tab,
+            ..Default::default()
+        };
+
+        let html = convert_markdown_to_html(content, &conf(None));
+        assert!(
+            html.contains("<a href=\"https://other.example.org/a\" title=\"Other\" rel=\"noopener noreferrer\">out</a>"),
+            "{html}"
+        );
+        assert!(
+            html.contains("<a href=\"https://Example.com/about\">home</a>"),
+            "{html}"
+        );
+        assert!(
+            html.contains("<a href=\"about.html\">relative</a>"),
+            "{html}"
+        );
+        assert!(html.contains("<a href=\"#top\">anchor</a>"), "{html}");
+        assert_eq!(html.matches("rel=").count(), 1);
+
+        let html = convert_markdown_to_html(content, &conf(Some(true)));
+        assert!(
+            html.contains("rel=\"noopener noreferrer\" target=\"_blank\">out</a>"),
+            "{html}"
+        );
+        assert_eq!(html.matches("target=").count(), 1);
+    }
+
+    #[test]
+    fn emoji_shortcodes_expand_only_in_prose() {
+        let content = "Launch :rocket: now :not_an_emoji: `:rocket:`\n\n```\n:rocket:\n```\n";
+        let conf = |emoji| Conf {
+            emoji,
+            highlight: Some(false),
+            ..Default::default()
+        };
+
+        let html = convert_markdown_to_html(content, &conf(Some(true)));
+        assert!(
+            html.contains("<p>Launch 🚀 now :not_an_emoji: <code>:rocket:</code></p>"),
+            "{html}"
+        );
+        assert!(
+            html.contains("<pre><code>:rocket:\n</code></pre>"),
+            "{html}"
+        );
+
+        let html = convert_markdown_to_html(content, &conf(None));
+        assert!(!html.contains('🚀'), "{html}");
+    }
+
+    #[test]
+    fn sanitizing_strips_scripts_handlers_and_javascript_urls() {
+        let content = "**Bold** <em onclick=\"steal()\">kept</em> [x](javascript:alert(1)) <a href=\"javascript:alert(2)\">y</a>\n\n<script>alert(3)</script>\n\n- [x] done\n\n```rust\nlet a = 1;\n```\n";
+        let conf = |sanitize_html| Conf {
+            sanitize_html,
+            ..Default::default()
+        };
+
+        let html = convert_markdown_to_html(content, &conf(Some(true)));
+        assert!(
+            html.contains("<strong>Bold</strong> <em>kept</em>"),
+            "{html}"
+        );
+        assert!(
+            !html.contains("onclick") && !html.contains("steal"),
+            "{html}"
+        );
+        assert!(!html.contains("javascript:"), "{html}");
+        assert!(
+            !html.contains("<script") && !html.contains("alert(3)"),
+            "{html}"
+        );
+        assert!(
+            html.contains("<input disabled=\"\" type=\"checkbox\" checked=\"\">"),
+            "{html}"
+        );
+        assert!(html.contains("<span style=\""), "{html}");
+        assert_eq!(convert_markdown_to_html("", &conf(Some(true))), "");
+
+        for sanitize_html in [None, Some(false)] {
+            let html = convert_markdown_to_html(content, &conf(sanitize_html));
+            assert!(html.contains("<script>alert(3)</script>"), "{html}");
+            assert!(html.contains("onclick=\"steal()\""), "{html}");
+        }
+    }
+
+    #[test]
+    fn line_numbers_wrap_each_line_and_honour_a_start() {
+        let conf = |code_line_numbers| Conf {
+            code_line_numbers,
+            ..Default::default()
+        };
+        let number = |n: usize| {
+            format!("<span class=\"line\"><span class=\"line-number\" style=\"user-select: none;\" aria-hidden=\"true\">{n} </span>")
+        };
+
+        let html =
+            convert_markdown_to_html("```rust\nlet a = 1;\nlet b = 2;\n```\n", &conf(Some(true)));
+        assert_eq!(html.matches("<span class=\"line\">").count(), 2, "{html}");
+        assert!(
+            html.contains(&number(1)) && html.contains(&number(2)),
+            "{html}"
+        );
+        assert!(html.trim_end().ends_with("</span></span></pre>"), "{html}");
+
+        let html = convert_markdown_to_html(
+            "```rust{start=10}\nlet a = 1;\nlet b = 2;\n```\n",
+            &conf(Some(true)),
+        );
+        assert!(
+            html.contains(&number(10)) && html.contains(&number(11)),
+            "{html}"
+        );
+        assert!(!html.contains(&number(1)), "{html}");
+
+        let html = convert_markdown_to_html("```rust\nlet a = 1;\n```\n", &conf(None));
+        assert!(!html.contains("line-number"), "{html}");
+    }
+
+    #[test]
+    fn wikilinks_link_to_documents_or_mark_broken_ones() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("other-post.md"), "# Other Post\n").unwrap();
+        std::fs::write(dir.path().join("Notes.md"), "# Notes\n").unwrap();
+        let conf = Conf {
+            document_dirs: Some(vec![dir.path().to_path_buf()]),
+            ..Default::default()
+        };
+
+        let html = convert_markdown_to_html(
+            "See [[Other Post]], [[Notes|my notes]] and [[Missing & Post]]. `[[Other Post]]`\n",
+            &conf,
+        );
+        assert!(
+            html.contains("See <a href=\"other-post.html\">Other Post</a>,"),
+            "{html}"
+        );
+        assert!(
+            html.contains("<a href=\"Notes.html\">my notes</a>"),
+            "{html}"
+        );
+        assert!(
+            html.contains("<span class=\"broken-link\" title=\"No document named Missing &amp; Post\">Missing &amp; Post</span>"),
+            "{html}"
+        );
+        assert!(html.contains("<code>[[Other Post]]</code>"), "{html}");
+    }
+}
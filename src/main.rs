@@ -1,6 +1,7 @@
 mod lib {
     pub mod feed;
     pub mod filesystem;
+    pub mod frontmatter;
     pub mod parse;
 }
 
@@ -24,7 +25,7 @@ See `adduce feed` for Adduce Feed usage.
 
 use config::html::Generate;
 pub use serde::{Deserialize, Serialize};
-use std::env;
+use std::{env, process};
 
 fn main() {
     let args: Vec<String> = env::args().skip(1).collect();
@@ -36,11 +37,14 @@ fn main() {
 
     // If the command line arguments contain "feed", process them with the feed module and return
     if args.contains(&String::from("feed")) {
-        lib::feed::process(args);
+        if let Err(e) = lib::feed::process(args) {
+            eprintln!("{e}");
+            process::exit(1);
+        }
         return;
     }
 
-    if args.len() % 2 != 0 {
+    if !args.len().is_multiple_of(2) {
         println!("Invalid arguments");
         return;
     }